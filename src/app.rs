@@ -1,26 +1,68 @@
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::Read;
 use std::io::BufReader;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use crate::config::Config;
+use crate::config::{Config, CONFIG_PATH};
 
 use egui;
 use egui::scroll_area::ScrollBarVisibility;
-use egui::{ImageSource, Label, Sense, TextBuffer, Vec2, Visuals};
+use egui::{ImageSource, Label, Rect, RichText, Sense, TextBuffer, Vec2, Visuals};
 use egui::ImageSource::Uri;
-use twilight_model::guild::Member;
+use twilight_model::channel::{ChannelType, Message};
+use twilight_model::guild::{Member, Role};
 use crate::discord::event_thread::Ticker;
-use crate::discord::jobs::{DeleteMessage, EditMessage, GetGuildPreview, GetMembers, GetMessages, Job, SendFile, SendMessage};
+use crate::discord::guild::Server;
+use crate::discord::jobs::{AddMemberRole, DeleteMessage, EditMessage, GetBans, GetGuild, GetGuildPreview, GetMembers, GetMessages, GetRoles, Job, RemoveMemberRole, ReorderChannels, SearchMembers, SendMessage};
 use crate::discord::jobs::GetChannels;
+use crate::app::age_gate::AgeGate;
+use crate::app::channel_editor::ChannelEditor;
+use crate::app::role_manager::RoleManager;
+use crate::app::moderation::{ModAction, Moderation, ModerationDialog};
+use crate::app::profile::Profile;
+use crate::app::mentions::{Mention, MentionContext};
+use crate::app::message_builder::MessageBuilder;
+use crate::app::downloads::Downloads;
+use crate::app::lightbox::Lightbox;
+use crate::app::message_heights::MessageHeights;
+use crate::app::links::LinkWarning;
+use crate::app::markdown::Clicked;
+use crate::app::attachments::AttachmentAction;
+use crate::app::media::MediaGate;
+use crate::app::unread::{ReadState, READ_STATE_PATH};
 
-use crate::discord::shared_cache::{ArcMutex, Queue, SharedCache};
+use crate::discord::shared_cache::{ArcMutex, CacheView, Queue, SharedCache};
 use crate::discord::util;
+use crate::time_format::{TimeFormat, TimeSettings};
+
+mod age_gate;
+mod animation;
+mod attachments;
+mod channel_editor;
+mod disk_cache;
+mod downloads;
+mod embed;
+mod lightbox;
+mod links;
+mod markdown;
+mod media;
+mod mentions;
+mod message_builder;
+mod message_group;
+mod message_heights;
+mod role_manager;
+mod unread;
+mod uploads;
+mod moderation;
+mod profile;
 
 pub struct DiscordApp {
     shared_cache: Arc<SharedCache>,
+    cache: CacheView, // what this frame draws, never waits on the event thread
     job_queue: ArcMutex<Queue<Job>>,
     config: Config,
+    time: TimeFormat,
 
     input_text: String,
     current_server: String,
@@ -33,7 +75,23 @@ pub struct DiscordApp {
     edited_message_id: u64,
     is_editing: bool,
     options_open: bool,
-    images_pasted: usize,
+    member_search: String,
+    new_media_domain: String,
+    dragged_channel: Option<u64>,
+    channel_editor: ChannelEditor,
+    role_manager: RoleManager,
+    moderation: Moderation,
+    profile: Profile,
+    message_builder: MessageBuilder,
+    downloads: Downloads,
+    lightbox: Lightbox,
+    link_warning: LinkWarning,
+    age_gate: AgeGate,
+    message_heights: MessageHeights,
+    member_groups: MemberGroups,
+    loaded_message_ids: HashSet<u64>, // media let through by "Load image"
+    read_state: ReadState,
+    activity_requested: HashSet<u64>, // servers
 
     longest_render: Duration,
 }
@@ -45,10 +103,15 @@ impl DiscordApp {
 
         // Implement dynamic scale changing?
         ctx.set_pixels_per_point(1.66);
+        if config.enable_cache {
+            disk_cache::install(ctx, config.image_cache_mb * 1024 * 1024);
+        }
 
         Self {
+            cache: CacheView::new(&shared_cache),
             shared_cache,
             job_queue,
+            time: TimeFormat::new(&config.time),
             config,
             input_text: "".into(),
             current_server: "".into(),
@@ -61,7 +124,23 @@ impl DiscordApp {
             edited_message_id: 0,
             is_editing: false,
             options_open: false,
-            images_pasted: 0,
+            member_search: "".into(),
+            new_media_domain: "".into(),
+            dragged_channel: None,
+            channel_editor: ChannelEditor::new(),
+            role_manager: RoleManager::new(),
+            moderation: Moderation::new(),
+            profile: Profile::new(),
+            message_builder: MessageBuilder::new(),
+            downloads: Downloads::new(),
+            lightbox: Lightbox::new(),
+            link_warning: LinkWarning::new(),
+            age_gate: AgeGate::new(),
+            message_heights: MessageHeights::default(),
+            member_groups: MemberGroups::default(),
+            loaded_message_ids: HashSet::new(),
+            read_state: ReadState::load(READ_STATE_PATH),
+            activity_requested: HashSet::new(),
 
             longest_render: Duration::from_nanos(1),
         }
@@ -96,10 +175,21 @@ impl DiscordApp {
 
     pub fn render(&mut self, ctx: &egui::Context) {
         let now = Instant::now();
+        self.cache.refresh(&self.shared_cache);
+        self.request_activity();
         self.left_most_panel(ctx);
         self.left_inner_panel(ctx);
         self.member_panel(ctx); //right most
         self.chat_panel(ctx); //middle
+        self.channel_editor_window(ctx);
+        self.role_manager_window(ctx);
+        self.moderation_windows(ctx);
+        self.profile_window(ctx);
+        self.message_builder_window(ctx);
+        self.downloads_window(ctx);
+        self.lightbox_window(ctx);
+        self.link_warning_window(ctx);
+        self.age_gate_window(ctx);
         let elapsed = now.elapsed();
         //println!("{:?} {:?}", elapsed, self.longest_render);
         if elapsed.gt(&self.longest_render) {
@@ -107,6 +197,17 @@ impl DiscordApp {
         }
     }
 
+    pub fn open_channel(&mut self, channel_id: u64, name: String) {
+        let nsfw = self.cache.channels.0.iter()
+            .any(|channel| channel.id.get() == channel_id && channel.nsfw == Some(true));
+        if self.age_gate.blocks(channel_id, &name, nsfw) {
+            return;
+        }
+        self.message_heights.clear();
+        self.current_channel = name;
+        self.selected_channel_id = channel_id;
+        self.append_job(Job::GetMessages(GetMessages::new(channel_id, 100)));
+    }
     pub fn append_job(&self, job: Job) {
         let mut queue_guard = self.job_queue.guard();
         (*queue_guard).push(job);
@@ -122,7 +223,7 @@ impl DiscordApp {
                 }
                 if ui.button("Servers").clicked() {
                     self.draw_type = DrawMode::Servers;
-                    if self.shared_cache.servers.guard().is_empty() {
+                    if self.cache.servers.is_empty() {
                         println!("Fetching servers..");
                         self.append_job(Job::GetServers);
                     }
@@ -137,10 +238,18 @@ impl DiscordApp {
                         }
                     }
                     DrawMode::Servers => {
-                        let servers = self.shared_cache.servers.guard();
+                        let servers = self.cache.servers.get();
                         for server in &*servers {
-                            let response = ui.add(Label::new(&server.name)
-                                .sense(Sense::click()));
+                            let unread = self.read_state.server(server.id, &self.cache.activity);
+                            let response = ui.horizontal(|ui| {
+                                let mut name = RichText::new(&server.name);
+                                if unread.unread {
+                                    name = name.strong();
+                                }
+                                let response = ui.add(Label::new(name).sense(Sense::click()));
+                                unread::badge(ui, unread);
+                                response
+                            }).inner;
                             if response.clicked() {
                                 self.selected_server_id = server.id;
                                 self.current_server = server.name.clone();
@@ -148,14 +257,34 @@ impl DiscordApp {
                                 let preview_job = GetGuildPreview::new(server.id);
                                 self.append_job(Job::GetChannels(channel_job));
                                 self.append_job(Job::GetGuildPreview(preview_job));
+                                self.append_job(Job::GetRoles(GetRoles::new(server.id)));
+                                self.append_job(Job::GetGuild(GetGuild::new(server.id)));
                             }
                             response.context_menu(|ui| {
+                                if unread.unread && ui.button("Mark as read").clicked() {
+                                    self.mark_server_read(server.id);
+                                    ui.close_menu();
+                                }
                                 if ui.button("Leave server (todo)").clicked() {
                                     ui.close_menu();
                                 }
                                 if ui.button("Server options (todo)").clicked() {
                                     ui.close_menu();
                                 }
+                                if self.selected_server_id == server.id && ui.button("Manage roles").clicked() {
+                                    self.role_manager.open = true;
+                                    ui.close_menu();
+                                }
+                                if self.selected_server_id == server.id && ui.button("Bans").clicked() {
+                                    self.moderation.bans_open = true;
+                                    // job_queue directly, the closure already borrows other fields mutably
+                                    self.job_queue.guard().push(Job::GetBans(GetBans::new(server.id)));
+                                    ui.close_menu();
+                                }
+                                if self.selected_server_id == server.id && ui.button("Audit log").clicked() {
+                                    self.moderation.audit_log_open = true;
+                                    ui.close_menu();
+                                }
                                 if ui.button("Copy server ID").clicked() {
                                     ui.output_mut(|o| o.copied_text = server.id.to_string());
                                     ui.close_menu();
@@ -180,49 +309,174 @@ impl DiscordApp {
                 .resizable(true);
 
             window.show(ctx, |ui| {
-                ui.menu_button("Time zone", |ui| {
+                egui::CollapsingHeader::new("Time").default_open(true).show(ui, |ui| {
+                    let mut settings = self.time.settings().clone();
+                    time_settings(ui, &mut settings);
+                    if settings != *self.time.settings() {
+                        self.config.time = settings;
+                        self.time = TimeFormat::new(&self.config.time);
+                    }
+                    if let Some(err) = self.time.error() {
+                        ui.colored_label(ui.visuals().error_fg_color, format!("{}, showing UTC", err));
+                    }
+                });
+                egui::CollapsingHeader::new("Media").show(ui, |ui| {
+                    media::policy_editor(ui, &mut self.config.media, &mut self.new_media_domain);
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Spoilers and NSFW media");
+                    media::reveal_mode_combo(ui, &mut self.config.spoilers);
+                });
+                egui::CollapsingHeader::new("Links").show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Browser");
+                        ui.add(egui::TextEdit::singleline(&mut self.config.browser_name)
+                            .hint_text("system default")
+                            .desired_width(120.0));
+                    });
+                    ui.label("Trusted domains");
+                    links::trusted_domains_editor(ui, &mut self.config.trusted_link_domains);
+                });
+                ui.separator();
+                if ui.button("Save settings").clicked() {
+                    if let Err(err) = self.config.save_config(CONFIG_PATH) {
+                        println!("Couldn't save config: {}", err);
+                    }
+                }
             });
-            if util::pasted_image(ctx) {
-                self.images_pasted += 1;
-            }
-            ui.label(format!("Pasted imgs: {}", self.images_pasted));
         });
     }
     pub fn left_inner_panel(&mut self, ctx: &egui::Context) {
         egui::SidePanel::left("channel_panel").show(ctx, |ui| {
-            ui.heading(&self.current_server);
+            ui.horizontal(|ui| {
+                ui.heading(&self.current_server);
+                if self.selected_server_id != 0 {
+                    ui.menu_button("+", |ui| {
+                        let kinds = [
+                            ("Text channel", ChannelType::GuildText),
+                            ("Voice channel", ChannelType::GuildVoice),
+                            ("Category", ChannelType::GuildCategory)
+                        ];
+                        for (label, kind) in kinds {
+                            if ui.button(label).clicked() {
+                                self.channel_editor = ChannelEditor::create(kind, None);
+                                self.channel_editor.open = true;
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                }
+            });
             ui.separator();
             egui::ScrollArea::vertical()
                 .auto_shrink([false, false])
                 .show(ui, |ui| { //show_rows
-                    let channels = self.shared_cache.channels.guard();
-                    for text_channel in &*channels.0 {
+                    let channels = self.cache.channels.get();
+                    let activity = self.cache.activity.get();
+                    let mut text_drop = None;
+                    let mut voice_drop = None;
+                    for (index, text_channel) in channels.0.iter().enumerate() {
                         let name = text_channel.name.clone().unwrap();
-                        let response = ui.add(Label::new(name.clone()).sense(Sense::click()));
-                        if response.clicked() {
+                        let unread = activity.get(&text_channel.id.get())
+                            .map(|channel| self.read_state.channel(text_channel.id.get(), channel))
+                            .unwrap_or_default();
+                        let response = ui.horizontal(|ui| {
+                            let mut label = RichText::new(&name);
+                            if unread.unread {
+                                label = label.strong();
+                            }
+                            let response = ui.add(Label::new(label).sense(Sense::click_and_drag()));
+                            unread::badge(ui, unread);
+                            response
+                        }).inner;
+                        let nsfw = text_channel.nsfw == Some(true);
+                        if response.clicked() && !self.age_gate.blocks(text_channel.id.get(), &name, nsfw) {
                             let channel_id = text_channel.id.get();
                             let job = Job::GetMessages(GetMessages::new(channel_id, 100));
                             self.current_channel = name;
                             self.selected_channel_id = channel_id;
+                            self.message_heights.clear();
                             self.append_job(job);
                         }
+                        if response.drag_started() {
+                            self.dragged_channel = Some(text_channel.id.get());
+                        }
+                        if self.dragged_channel.is_some() && ui.rect_contains_pointer(response.rect) {
+                            text_drop = Some(index);
+                            ui.painter().hline(response.rect.x_range(), response.rect.top(), ui.visuals().selection.stroke);
+                        }
+                        response.context_menu(|ui| {
+                            if unread.unread && ui.button("Mark as read").clicked() {
+                                if let Some(channel) = activity.get(&text_channel.id.get()) {
+                                    self.mark_read(text_channel.id.get(), channel.last_message_id);
+                                }
+                                ui.close_menu();
+                            }
+                            if ui.button("Edit channel").clicked() {
+                                self.channel_editor = ChannelEditor::edit(text_channel);
+                                ui.close_menu();
+                            }
+                            if ui.button("Copy channel ID").clicked() {
+                                ui.output_mut(|o| o.copied_text = text_channel.id.get().to_string());
+                                ui.close_menu();
+                            }
+                        });
                     }
                     ui.separator();
-                    for voice in &*channels.1 {
+                    for (index, voice) in channels.1.iter().enumerate() {
                         let name = &voice.name.to_owned().unwrap();
                         let ui_name = format!("{} [{:?}/{}]", name, voice.member_count.unwrap_or(0), voice.user_limit.unwrap());
-                        let response = ui.add(Label::new(ui_name).sense(Sense::click()));
+                        let response = ui.add(Label::new(ui_name).sense(Sense::click_and_drag()));
+                        if response.drag_started() {
+                            self.dragged_channel = Some(voice.id.get());
+                        }
+                        if self.dragged_channel.is_some() && ui.rect_contains_pointer(response.rect) {
+                            voice_drop = Some(index);
+                            ui.painter().hline(response.rect.x_range(), response.rect.top(), ui.visuals().selection.stroke);
+                        }
                         response.context_menu(|ui| {
                             let _ = ui.button("Join (todo)");
-                            let _ = ui.button("Edit (todo)");
+                            if ui.button("Edit channel").clicked() {
+                                self.channel_editor = ChannelEditor::edit(voice);
+                                ui.close_menu();
+                            }
                         });
                     }
+                    let Some(dragged) = self.dragged_channel else {
+                        return;
+                    };
+                    if !ui.input(|i| i.pointer.any_released()) {
+                        return;
+                    }
+                    self.dragged_channel = None;
+                    // published right away, the request goes out after
+                    let moved = self.shared_cache.channels.update(|channels| {
+                        let moved_list = match (text_drop, voice_drop) {
+                            (Some(target), _) if channel_editor::move_channel(&mut channels.0, dragged, target) => &channels.0,
+                            (_, Some(target)) if channel_editor::move_channel(&mut channels.1, dragged, target) => &channels.1,
+                            _ => return None,
+                        };
+                        Some(moved_list.iter().map(|channel| (channel.id.get(), channel.parent_id.map(|id| id.get()))).collect())
+                    });
+                    let Some(moved_channels) = moved else {
+                        return;
+                    };
+                    let job = ReorderChannels::new(self.selected_server_id, moved_channels);
+                    self.append_job(Job::ReorderChannels(job));
                 });
         });
     }
     pub fn chat_panel(&mut self, ctx: &egui::Context) {
+        self.receive_files(ctx);
+        // what's shown in the open channel counts as read
+        let newest = self.cache.messages.first()
+            .filter(|msg| msg.channel_id.get() == self.selected_channel_id)
+            .map(|msg| msg.id.get());
+        if let Some(message_id) = newest {
+            self.mark_read(self.selected_channel_id, message_id);
+        }
         egui::TopBottomPanel::bottom("message_panel").show(ctx, |ui| {
+            self.pending_files_bar(ui);
             ui.horizontal(|ui| {
                 let input_field = egui::TextEdit::multiline(&mut self.input_text)
                     .min_size(Vec2::new(30.0, 30.0))
@@ -232,7 +486,8 @@ impl DiscordApp {
                 let submitted = ui.input(|i| {
                     i.key_pressed(egui::Key::Enter) && !i.modifiers.shift
                 });
-                if !self.input_text.is_empty() && self.selected_channel_id != 0 && submitted {
+                let has_files = !self.shared_cache.pending_files.load().is_empty();
+                if (!self.input_text.is_empty() || has_files) && self.selected_channel_id != 0 && submitted {
                     response.surrender_focus();
                     response.request_focus();
                     if self.is_editing && self.edited_message_id != 0 && !self.input_text.is_empty() {
@@ -244,6 +499,10 @@ impl DiscordApp {
                         self.is_editing = false;
                         self.append_job(Job::EditMessage(msg_edit));
                         self.input_text.truncate(0);
+                    } else if has_files {
+                        if self.files_fit() {
+                            self.send_pending_files(ui.ctx());
+                        }
                     } else {
                         let job;
                         let taken_text = std::mem::take(&mut self.input_text); // yoinked
@@ -260,88 +519,228 @@ impl DiscordApp {
                     let job = Job::SelectFile;
                     self.append_job(job);
                 }
-                let mut file_bytes = self.shared_cache.file_bytes.guard();
-                let mut file_name = self.shared_cache.file_name.guard();
-                if !file_bytes.is_empty() && !file_name.is_empty() && self.selected_channel_id != 0 &&
-                    ui.button("Send").clicked() {
-                    let target_bytes = std::mem::take(&mut *file_bytes);
-                    let target_name = std::mem::take(&mut *file_name);
-                    let job = SendFile::new(self.selected_channel_id, target_name, target_bytes);
-                    self.append_job(Job::SendFile(job));
+                if ui.button("Embed").on_hover_text("Build a message with embeds and components").clicked() {
+                    self.message_builder.open = !self.message_builder.open;
+                }
+                if has_files && self.selected_channel_id != 0 &&
+                    ui.add_enabled(self.files_fit(), egui::Button::new("Send")).clicked() {
+                    self.send_pending_files(ui.ctx());
                 }
             });
         });
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading(&self.current_channel);
+            ui.horizontal(|ui| {
+                ui.heading(&self.current_channel);
+                if !self.shared_cache.downloads.guard().is_empty() {
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("Downloads").clicked() {
+                            self.downloads.open = !self.downloads.open;
+                        }
+                    });
+                }
+            });
             ui.separator();
 
             let scroll = egui::ScrollArea::vertical().auto_shrink([false, false]).stick_to_bottom(true);
-            scroll.show(ui, |ui| { //show_rows
-                let mut messages = self.shared_cache.messages.guard();
+            let mut heights = std::mem::take(&mut self.message_heights);
+            let mut loaded_ids = std::mem::take(&mut self.loaded_message_ids);
+            let profile = scroll.show_viewport(ui, |ui, viewport| {
+                let messages = self.cache.messages.get();
                 if messages.is_empty() {
-                    return;
+                    return (None, None, vec![], None, None);
                 }
+                let servers = self.cache.servers.get();
+                let server = servers.iter().find(|server| server.id == self.selected_server_id);
+                let members = self.cache.members.get();
+                let channels = self.cache.channels.get();
+                let mut profile = None;
+                let mut open_channel = None;
+                let mut download = vec![];
+                let mut view = None;
+                let mut link = None;
                 let mut reply = None;
                 let mut edit_id = 0;
                 let mut is_editing = false;
                 let mut edited_text = "".into();
-                let mut rendered_images = 0;
-                let mut message_ids = self.shared_cache.rendered_msg_ids.guard();
-                for msg in messages.iter_mut() {
-                    if !msg.attachments.is_empty() {
-                        msg.attachments[0].url = util::strip_parameters(msg.attachments[0].url.to_owned());
-                    }
-                }
+                let nsfw = channels.0.iter()
+                    .any(|channel| channel.id.get() == self.selected_channel_id && channel.nsfw == Some(true));
+                let media = MediaGate::new(&self.config.media, &self.shared_cache.loaded_media)
+                    .spoilers(self.config.spoilers, nsfw);
+                let mut previous = None;
+                let origin = ui.max_rect().min;
+                let width = ui.available_width();
+                let mut top = 0.0;
                 for msg in messages.iter().rev() {
-                    let text = util::format_message(&msg);
-                    let response = ui.add(Label::new(&text).sense(Sense::click()));
-                    if !msg.attachments.is_empty() && message_ids.contains(&msg.id.get())  {
-                        let link = &msg.attachments[0].url;
-                        if util::is_domain_trusted(link) && util::is_supported_media(link) {
-                            rendered_images += 1;
-
-                            ui.add(egui::Image::new(Uri(link.into()))
-                                .fit_to_original_size(0.5)
-                                .max_size(Vec2::new(350f32, 350f32))
-                                .rounding(5.0));
-                        }
+                    let secs = msg.timestamp.as_secs();
+                    let divider = previous.is_none_or(|previous: &Message| !self.time.same_day(previous.timestamp.as_secs(), secs));
+                    let header = message_group::starts_group(previous, msg, &self.time);
+                    previous = Some(msg);
+                    let key = message_heights::layout_key(msg, header, divider, width);
+                    let height = heights.get(msg.id.get(), key).unwrap_or(message_heights::ESTIMATED_HEIGHT);
+                    // messages out of view are only counted by their height
+                    if top + height < viewport.min.y || top > viewport.max.y {
+                        top += height;
+                        continue;
                     }
-                    response.context_menu(|ui| {
-                        if ui.button("Reply").clicked() {
-                            reply = Some(msg.id.get());
-                            ui.close_menu();
-                        }
-                        if ui.button("Copy text").clicked() {
-                            ui.output_mut(|o| o.copied_text = text.clone());
-                            ui.close_menu(); //TODO: make selectable?
-                        }
-                        if !msg.attachments.is_empty() && !(*message_ids).contains(&msg.id.get()) && ui.button("Load image").clicked() {
-                            message_ids.push(msg.id.get());
-                            ui.close_menu(); //TODO: make selectable?
+                    let row = Rect::from_min_size(origin + Vec2::new(0.0, top), Vec2::new(width, f32::INFINITY));
+                    // keyed by the message so widget ids don't shift while scrolling
+                    let shown = ui.child_ui_with_id_source(row, *ui.layout(), msg.id.get()).scope(|ui| {
+                        let context = MentionContext {
+                            server,
+                            members: &members,
+                            channels: &channels,
+                            users: &msg.mentions,
+                            time: &self.time,
+                        };
+                        if divider {
+                            message_group::date_divider(ui, secs, &self.time);
                         }
-                        if ui.button("Edit message").clicked() {
-                            is_editing = true;
-                            edit_id = msg.id.get();
-                            edited_text = msg.content.clone();
-                            ui.close_menu();
+                        if header {
+                            ui.add_space(6.0);
                         }
-                        let _ = ui.button(format!("Attachments: {}", msg.attachments.len()));
-                        if msg.reference.is_some() {
-                            let id = msg.reference.clone().unwrap().message_id.unwrap();
-                            let _ = ui.button(format!("Replies: {}", id.to_string()));
-                        }
-                        if ui.button("Copy message ID").clicked() {
-                            ui.output_mut(|o| o.copied_text = msg.id.get().to_string());
-                            ui.close_menu(); //TODO: make selectable?
+                        let member = members.iter().find(|member| member.user.id == msg.author.id);
+                        let gate = media.load_all(loaded_ids.contains(&msg.id.get()));
+                        let (author_clicked, response, clicked) = ui.horizontal_top(|ui| {
+                            let mut author_clicked = false;
+                            if header {
+                                author_clicked = message_group::avatar(ui, msg, member, self.selected_server_id).clicked();
+                            } else {
+                                ui.add_space(message_group::AVATAR_SIZE);
+                            }
+                            ui.vertical(|ui| {
+                                if header {
+                                    author_clicked |= message_group::author_line(ui, msg, member, server, &self.time).clicked();
+                                }
+                                let (response, mut clicked) = ui.horizontal_wrapped(|ui| {
+                                    let body = markdown::layout_job(ui.ctx(), ui.style(), &msg.content, &context);
+                                    let shown = markdown::show(ui, body, media.reveal_mode());
+                                    message_group::edited_marker(ui, msg, &self.time);
+                                    shown
+                                }).inner;
+                                for sticker in &msg.sticker_items {
+                                    match util::sticker_url(sticker) {
+                                        Some(url) if gate.allows(&url, None) => {
+                                            let (rect, response) = ui.allocate_exact_size(Vec2::splat(160.0), Sense::hover());
+                                            if ui.is_rect_visible(rect) {
+                                                animation::paint_at(ui, &url, rect);
+                                            }
+                                            response.on_hover_text(&sticker.name);
+                                        }
+                                        Some(url) => gate.placeholder(ui, &url, None, &format!("sticker: {}", sticker.name)),
+                                        None => {
+                                            ui.weak(format!("[sticker: {}]", sticker.name));
+                                        }
+                                    }
+                                }
+                                match attachments::show_attachments(ui, &msg.attachments, &gate) {
+                                    Some(AttachmentAction::Download(attachment)) => download.push(attachment.clone()),
+                                    Some(AttachmentAction::View(attachment)) => view = Some(attachment.clone()),
+                                    None => {}
+                                }
+                                for embed in &msg.embeds {
+                                    if let Some(click) = embed::show_embed(ui, embed, &gate, &context) {
+                                        clicked = Some(click);
+                                    }
+                                }
+                                (author_clicked, response, clicked)
+                            }).inner
+                        }).inner;
+                        if author_clicked {
+                            profile = Some(match member {
+                                Some(member) => Profile::from_member(member),
+                                None => Profile::from_author(msg),
+                            });
                         }
-                        if ui.button("Delete message").clicked() {
-                            let job = Job::DeleteMessage(DeleteMessage::new(msg.channel_id.get(), msg.id.get()));
-                            self.append_job(job);
-                            ui.close_menu();
+                        match clicked {
+                            Some(Clicked::Mention(Mention::User(id))) => {
+                                if let Some(member) = members.iter().find(|member| member.user.id == id) {
+                                    profile = Some(Profile::from_member(member));
+                                } else if let Some(user) = msg.mentions.iter().find(|user| user.id == id) {
+                                    profile = Some(Profile::from_mention(user));
+                                }
+                            }
+                            Some(Clicked::Mention(Mention::Channel(id))) => {
+                                let channel = channels.0.iter().find(|channel| channel.id == id);
+                                open_channel = channel.map(|channel| (id, channel.name.clone().unwrap_or_default()));
+                            }
+                            Some(Clicked::Link(clicked_link)) => link = Some(clicked_link),
+                            _ => {}
                         }
-                    });
-                    ui.separator();
+                        response.context_menu(|ui| {
+                            if ui.button("Reply").clicked() {
+                                reply = Some(msg.id.get());
+                                ui.close_menu();
+                            }
+                            if ui.button("Copy text").clicked() {
+                                ui.output_mut(|o| o.copied_text = util::format_message(msg));
+                                ui.close_menu(); //TODO: make selectable?
+                            }
+                            let has_images = !msg.attachments.is_empty() || msg.embeds.iter().any(embed::has_images);
+                            if has_images && !loaded_ids.contains(&msg.id.get()) && ui.button("Load image").clicked() {
+                                loaded_ids.insert(msg.id.get());
+                                ui.close_menu(); //TODO: make selectable?
+                            }
+                            if ui.button("Edit message").clicked() {
+                                is_editing = true;
+                                edit_id = msg.id.get();
+                                edited_text = msg.content.clone();
+                                ui.close_menu();
+                            }
+                            let _ = ui.button(format!("Attachments: {}", msg.attachments.len()));
+                            match msg.attachments.as_slice() {
+                                [] => {}
+                                [attachment] => {
+                                    if ui.button("Save attachment").clicked() {
+                                        download.push(attachment.clone());
+                                        ui.close_menu();
+                                    }
+                                }
+                                attachments => {
+                                    ui.menu_button("Save attachment", |ui| {
+                                        for attachment in attachments {
+                                            if ui.button(&attachment.filename).clicked() {
+                                                download.push(attachment.clone());
+                                                ui.close_menu();
+                                            }
+                                        }
+                                        ui.separator();
+                                        if ui.button(format!("All {}", attachments.len())).clicked() {
+                                            download.extend(attachments.iter().cloned());
+                                            ui.close_menu();
+                                        }
+                                    });
+                                }
+                            }
+                            // only what's loaded in the channel so far
+                            if ui.button("Save all attachments in channel").clicked() {
+                                download.extend(messages.iter().flat_map(|msg| msg.attachments.iter().cloned()));
+                                ui.close_menu();
+                            }
+                            if ui.button("Save all from this user").clicked() {
+                                let from_author = messages.iter().filter(|other| other.author.id == msg.author.id);
+                                download.extend(from_author.flat_map(|msg| msg.attachments.iter().cloned()));
+                                ui.close_menu();
+                            }
+                            if msg.reference.is_some() {
+                                let id = msg.reference.clone().unwrap().message_id.unwrap();
+                                let _ = ui.button(format!("Replies: {}", id.to_string()));
+                            }
+                            if ui.button("Copy message ID").clicked() {
+                                ui.output_mut(|o| o.copied_text = msg.id.get().to_string());
+                                ui.close_menu(); //TODO: make selectable?
+                            }
+                            if ui.button("Delete message").clicked() {
+                                let job = Job::DeleteMessage(DeleteMessage::new(msg.channel_id.get(), msg.id.get()));
+                                self.append_job(job);
+                                ui.close_menu();
+                            }
+                        });
+                    }).response;
+                    let height = shown.rect.height() + ui.spacing().item_spacing.y;
+                    heights.insert(msg.id.get(), key, height);
+                    top += height;
                 }
+                ui.set_min_height(top);
                 if let Some(id) = reply {
                     self.reply_message_id = id;
                 }
@@ -350,33 +749,147 @@ impl DiscordApp {
                     self.edited_message_id = edit_id;
                     self.input_text = edited_text;
                 }
-            });
+                (profile, open_channel, download, view, link)
+            }).inner;
+            self.message_heights = heights;
+            self.loaded_message_ids = loaded_ids;
+            let (profile, open_channel, download, view, link) = profile;
+            if let Some(link) = link {
+                self.follow_link(link);
+            }
+            if let Some(attachment) = view {
+                self.lightbox.open(attachment);
+            }
+            if !download.is_empty() {
+                self.download_attachments(&download);
+            }
+            if let Some(profile) = profile {
+                self.open_profile(profile);
+            }
+            if let Some((channel_id, name)) = open_channel {
+                self.open_channel(channel_id, name);
+            }
         });
     }
-    pub fn member_panel(&self, ctx: &egui::Context) {
+    pub fn member_panel(&mut self, ctx: &egui::Context) {
         egui::SidePanel::right("member_panel").show(ctx, |ui| {
-            if ui.button("Fetch members").clicked() && self.selected_server_id != 0 {
-                let job = Job::GetMembers(GetMembers::new(self.selected_server_id, 200));
-                self.append_job(job);
+            ui.horizontal(|ui| {
+                if ui.button("Fetch members").clicked() && self.selected_server_id != 0 {
+                    let job = Job::GetMembers(GetMembers::new(self.selected_server_id, 1000));
+                    self.append_job(job);
+                }
+                ui.label(format!("{} fetched", self.cache.members.len()));
+            });
+            let search = ui.add(egui::TextEdit::singleline(&mut self.member_search)
+                .hint_text("Search members"));
+            if search.changed() && self.member_search.is_empty() {
+                self.shared_cache.member_search.store(vec![]);
+            }
+            if search.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let query = self.member_search.trim();
+                if !query.is_empty() && self.selected_server_id != 0 {
+                    let job = SearchMembers::new(self.selected_server_id, query.into(), 100);
+                    self.append_job(Job::SearchMembers(job));
+                }
             }
+            let searching = !self.member_search.trim().is_empty();
             egui::ScrollArea::vertical()
                 .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
                 .auto_shrink([false, false])
                 .show(ui, |ui| {
-                    let members = self.shared_cache.members.guard();
-                    for member in &*members {
-                        let response = ui.add(Label::new(&member.user.name).sense(Sense::click()));
-                        response.context_menu(|ui| {
-                            if ui.button("Copy name").clicked() {
-                                ui.output_mut(|o| o.copied_text = member.user.name.clone());
-                                ui.close_menu()
-                            }
-                            let _ = ui.button("Open DM");
-                            if ui.button("Copy ID").clicked() {
-                                ui.output_mut(|o| o.copied_text = member.user.id.get().to_string());
-                                ui.close_menu()
+                    let servers = self.cache.servers.get();
+                    let server = servers.iter().find(|server| server.id == self.selected_server_id);
+                    let members = if searching {
+                        self.cache.member_search.get()
+                    } else {
+                        self.cache.members.get()
+                    };
+                    if searching && members.is_empty() {
+                        ui.weak("Press enter to search");
+                    }
+                    let mut moderation_dialog = None;
+                    let mut profile = None;
+                    let groups = self.member_groups.get(&members, &servers, self.selected_server_id);
+                    for (group_name, group) in groups.iter() {
+                        ui.add_space(4.0);
+                        ui.label(RichText::new(format!("{} - {}", group_name, group.len())).small().strong());
+                        for member in group.iter().map(|index| &members[*index]) {
+                            let response = ui.horizontal(|ui| {
+                                let avatar_size = Vec2::splat(20.0);
+                                // only visible avatars are requested, large servers have thousands
+                                let row = Rect::from_min_size(ui.cursor().min, Vec2::new(ui.available_width(), avatar_size.y));
+                                if ui.is_rect_visible(row) {
+                                    let avatar = util::avatar_url(member, self.selected_server_id, 32);
+                                    ui.add(egui::Image::new(Uri(avatar.into()))
+                                        .fit_to_exact_size(avatar_size)
+                                        .rounding(avatar_size.x / 2.0));
+                                } else {
+                                    ui.allocate_space(avatar_size);
+                                }
+                                let mut text = RichText::new(util::display_name(member));
+                                if let Some(color) = server.and_then(|server| util::role_color(server.member_color(member))) {
+                                    text = text.color(color);
+                                }
+                                ui.add(Label::new(text).sense(Sense::click()))
+                            }).inner;
+                            if response.clicked() {
+                                profile = Some(Profile::from_member(member));
                             }
-                        });
+                            response.context_menu(|ui| {
+                                if ui.button("Copy name").clicked() {
+                                    ui.output_mut(|o| o.copied_text = member.user.name.clone());
+                                    ui.close_menu()
+                                }
+                                let _ = ui.button("Open DM");
+                                if ui.button("Copy ID").clicked() {
+                                    ui.output_mut(|o| o.copied_text = member.user.id.get().to_string());
+                                    ui.close_menu()
+                                }
+                                let Some(server) = server else {
+                                    return;
+                                };
+                                ui.separator();
+                                let actions = [("Kick", ModAction::Kick), ("Ban", ModAction::Ban), ("Timeout", ModAction::Timeout)];
+                                for (label, action) in actions {
+                                    if ui.button(label).clicked() {
+                                        let dialog = ModerationDialog::new(action, server.id, member.user.id.get(), member.user.name.clone());
+                                        moderation_dialog = Some(dialog);
+                                        ui.close_menu();
+                                    }
+                                }
+                                ui.menu_button("Roles", |ui| {
+                                    for role in &server.roles {
+                                        // @everyone and integration roles can't be assigned
+                                        if role.id == server.id || role.managed {
+                                            continue;
+                                        }
+                                        let had_role = member.roles.contains(&role.id);
+                                        let mut has_role = had_role;
+                                        let mut text = egui::RichText::new(&role.name);
+                                        if let Some(color) = util::role_color(role.color) {
+                                            text = text.color(color);
+                                        }
+                                        ui.checkbox(&mut has_role, text);
+                                        let (user_id, role_id) = (member.user.id.get(), role.id.get());
+                                        if has_role && !had_role {
+                                            let job = AddMemberRole::new(server.id, user_id, role_id);
+                                            self.append_job(Job::AddMemberRole(job));
+                                        } else if !has_role && had_role {
+                                            let job = RemoveMemberRole::new(server.id, user_id, role_id);
+                                            self.append_job(Job::RemoveMemberRole(job));
+                                        }
+                                    }
+                                });
+                            });
+                        }
+                    }
+                    if moderation_dialog.is_some() {
+                        self.moderation.dialog = moderation_dialog;
+                    }
+                    drop(members);
+                    drop(servers);
+                    if let Some(profile) = profile {
+                        self.open_profile(profile);
                     }
                     ui.separator();
                 });
@@ -384,6 +897,67 @@ impl DiscordApp {
     }
 }
 
+type Groups = Vec<(String, Vec<usize>)>; // group names and indices into the members
+type GroupsKey = (Arc<Vec<Member>>, Arc<Vec<Server>>, u64); // held so the pointers can't be reused
+
+// The grouping is redone only when the members, the servers or the selected server change
+#[derive(Default)]
+struct MemberGroups {
+    key: Option<GroupsKey>,
+    groups: Arc<Groups>,
+}
+impl MemberGroups {
+    fn get(&mut self, members: &Arc<Vec<Member>>, servers: &Arc<Vec<Server>>, server_id: u64) -> Arc<Groups> {
+        let fresh = self.key.as_ref().is_some_and(|(cached_members, cached_servers, cached_id)| {
+            Arc::ptr_eq(cached_members, members) && Arc::ptr_eq(cached_servers, servers) && *cached_id == server_id
+        });
+        if !fresh {
+            let server = servers.iter().find(|server| server.id == server_id);
+            self.groups = Arc::new(group_members(server, members));
+            self.key = Some((members.clone(), servers.clone(), server_id));
+        }
+        self.groups.clone()
+    }
+}
+
+// Groups members under their highest hoisted role, the rest go under "Members"
+fn group_members(server: Option<&Server>, members: &[Member]) -> Groups {
+    let hoisted: Vec<&Role> = match server {
+        Some(server) => server.roles.iter().filter(|role| role.hoist).collect(),
+        None => vec![],
+    };
+    let mut groups: Vec<(String, Vec<usize>)> = hoisted.iter()
+        .map(|role| (role.name.clone(), vec![]))
+        .collect();
+    let mut rest = vec![];
+    for (index, member) in members.iter().enumerate() {
+        let group = server
+            .and_then(|server| server.hoisted_role(member))
+            .and_then(|role| hoisted.iter().position(|hoisted| hoisted.id == role.id));
+        match group {
+            Some(group) => groups[group].1.push(index),
+            None => rest.push(index),
+        }
+    }
+    groups.push(("Members".into(), rest));
+    groups.retain(|(_, group)| !group.is_empty());
+    for (_, group) in &mut groups {
+        group.sort_by_cached_key(|index| util::display_name(&members[*index]).to_lowercase());
+    }
+    groups
+}
+
+fn time_settings(ui: &mut egui::Ui, settings: &mut TimeSettings) {
+    ui.horizontal(|ui| {
+        ui.label("Time zone");
+        ui.add(egui::TextEdit::singleline(&mut settings.zone)
+            .hint_text("Europe/Warsaw, Local or UTC+2")
+            .desired_width(150.0));
+    });
+    ui.checkbox(&mut settings.clock_24h, "24-hour clock");
+    ui.checkbox(&mut settings.relative, "Relative times (5 minutes ago)");
+}
+
 #[derive(PartialEq)]
 enum DrawMode {
     Friends,
@@ -0,0 +1,57 @@
+use std::collections::HashSet;
+use egui::{Context, RichText};
+use crate::app::DiscordApp;
+
+// NSFW channels are confirmed once per session before their messages are fetched
+pub struct AgeGate {
+    pending: Option<(u64, String)>, // the channel waiting for confirmation
+    confirmed: HashSet<u64>,
+}
+
+impl AgeGate {
+    pub fn new() -> Self {
+        Self{
+            pending: None,
+            confirmed: HashSet::new(),
+        }
+    }
+
+    // Returns true if the channel has to be confirmed first
+    pub fn blocks(&mut self, channel_id: u64, name: &str, nsfw: bool) -> bool {
+        if !nsfw || self.confirmed.contains(&channel_id) {
+            return false;
+        }
+        self.pending = Some((channel_id, name.to_string()));
+        true
+    }
+}
+
+impl DiscordApp {
+    pub fn age_gate_window(&mut self, ctx: &Context) {
+        let Some((channel_id, name)) = self.age_gate.pending.clone() else {
+            return;
+        };
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Window::new("Age-restricted channel")
+            .id(egui::Id::new("age_gate_window"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(RichText::new(format!("#{} is marked as NSFW", name)).strong());
+                ui.label("It may contain content that isn't suitable for everyone. You must be 18 or older to view it.");
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("I'm 18 or older, continue").clicked();
+                    cancelled = ui.button("Go back").clicked();
+                });
+            });
+        if confirmed {
+            self.age_gate.confirmed.insert(channel_id);
+            self.age_gate.pending = None;
+            self.open_channel(channel_id, name);
+        } else if cancelled {
+            self.age_gate.pending = None;
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use egui::{Color32, ColorImage, Context, Id, Pos2, Rect, TextureHandle, TextureOptions, Ui, Vec2};
+use egui::load::BytesPoll;
+use image::{AnimationDecoder, Frames, ImageDecoder, ImageFormat};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+
+// The egui loaders only decode the first frame, animated formats are decoded here instead.
+// Decoding runs on its own thread, the first frame is shown by the regular loader meanwhile.
+// Frames are kept in a cache in the context's temporary data, the least recently painted go first.
+
+const CACHE_BYTES: usize = 128 * 1024 * 1024; // of decoded frames
+const MAX_ENTRIES: usize = 512;
+const RETRY_AFTER: Duration = Duration::from_secs(30); // for images that failed to decode
+
+struct Animation {
+    frames: Vec<(TextureHandle, u64)>,
+    duration_ms: u64,
+    bytes: usize,
+}
+
+// What the decoding thread found, frames aren't uploaded until they're known to fit
+enum Decoded {
+    Frames(Vec<(ColorImage, u64)>, usize), // with their delay in ms, and their total bytes
+    TooLarge,
+}
+
+enum Entry {
+    Decoding,
+    Animated(Arc<Animation>),
+    Static, // a single frame or not an animated format, left to the regular loader
+    Failed(Instant),
+}
+
+struct Cached {
+    entry: Entry,
+    last_used: u64, // frame number
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<String, Cached>,
+    bytes: usize,
+}
+
+impl Cache {
+    fn insert(&mut self, url: String, entry: Entry, last_used: u64) {
+        if let Entry::Animated(animation) = &entry {
+            self.bytes += animation.bytes;
+        }
+        if let Some(Cached { entry: Entry::Animated(old), .. }) = self.entries.insert(url, Cached { entry, last_used }) {
+            self.bytes -= old.bytes;
+        }
+        self.evict();
+    }
+
+    // Entries still decoding are kept, their thread is about to fill them in
+    fn evict(&mut self) {
+        while self.bytes > CACHE_BYTES || self.entries.len() > MAX_ENTRIES {
+            let oldest = self.entries.iter()
+                .filter(|(_, cached)| !matches!(cached.entry, Entry::Decoding))
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(url, _)| url.clone());
+            let Some(url) = oldest else {
+                break;
+            };
+            if let Some(Cached { entry: Entry::Animated(animation), .. }) = self.entries.remove(&url) {
+                self.bytes -= animation.bytes;
+            }
+        }
+    }
+}
+
+type SharedCache = Arc<Mutex<Cache>>;
+
+enum Loaded {
+    Animated(Arc<Animation>),
+    Static,
+    Pending, // still downloading
+}
+
+// Paints the image at `url` into `rect`, animating GIF, APNG and WebP images
+pub fn paint_at(ui: &mut Ui, url: &str, rect: Rect) {
+    match load(ui.ctx(), url) {
+        Loaded::Animated(animation) => {
+            let texture = current_frame(ui.ctx(), &animation);
+            let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+            ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
+        }
+        Loaded::Static => egui::Image::new(url.to_string()).paint_at(ui, rect),
+        Loaded::Pending => {
+            ui.painter().rect_filled(rect, 3.0, ui.visuals().faint_bg_color);
+        }
+    }
+}
+
+// Size of the frames, None if the image isn't animated or not decoded yet
+pub fn frame_size(ctx: &Context, url: &str) -> Option<Vec2> {
+    match load(ctx, url) {
+        Loaded::Animated(animation) => animation.frames.first().map(|(texture, _)| texture.size_vec2()),
+        _ => None,
+    }
+}
+
+fn cache(ctx: &Context) -> SharedCache {
+    ctx.data_mut(|data| data.get_temp_mut_or_default::<SharedCache>(Id::new("animations")).clone())
+}
+
+fn lock(cache: &SharedCache) -> MutexGuard<'_, Cache> {
+    match cache.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn load(ctx: &Context, url: &str) -> Loaded {
+    let cache = cache(ctx);
+    let frame = ctx.frame_nr();
+    if let Some(cached) = lock(&cache).entries.get_mut(url) {
+        cached.last_used = frame;
+        match &cached.entry {
+            Entry::Decoding | Entry::Static => return Loaded::Static,
+            Entry::Animated(animation) => return Loaded::Animated(animation.clone()),
+            Entry::Failed(at) if at.elapsed() < RETRY_AFTER => return Loaded::Static,
+            Entry::Failed(_) => {}
+        }
+    }
+    let bytes = match ctx.try_load_bytes(url) {
+        Ok(BytesPoll::Ready { bytes, .. }) => bytes,
+        Ok(BytesPoll::Pending { .. }) => return Loaded::Pending,
+        // not cached, the static path shows the error and the next load tries again
+        Err(_) => return Loaded::Static,
+    };
+    if !is_animated(&bytes) {
+        lock(&cache).insert(url.to_string(), Entry::Static, frame);
+        return Loaded::Static;
+    }
+    lock(&cache).insert(url.to_string(), Entry::Decoding, frame);
+    let (ctx, url) = (ctx.clone(), url.to_string());
+    thread::spawn(move || {
+        let entry = match decode_frames(&bytes) {
+            Some(Decoded::Frames(images, bytes)) if images.len() >= 2 => {
+                let duration_ms = images.iter().map(|(_, delay)| delay).sum();
+                let frames = images.into_iter().enumerate().map(|(index, (image, delay))| {
+                    (ctx.load_texture(format!("{}#{}", url, index), image, TextureOptions::LINEAR), delay)
+                }).collect();
+                Entry::Animated(Arc::new(Animation { frames, duration_ms, bytes }))
+            }
+            // one that wouldn't fit even on its own stays still
+            Some(_) => Entry::Static,
+            None => Entry::Failed(Instant::now()),
+        };
+        let mut cache = lock(&cache);
+        let last_used = cache.entries.get(&url).map_or(0, |cached| cached.last_used);
+        cache.insert(url, entry, last_used);
+        drop(cache);
+        ctx.request_repaint();
+    });
+    Loaded::Static
+}
+
+// Only reads the headers, so it's cheap enough for the UI thread
+fn is_animated(bytes: &[u8]) -> bool {
+    match image::guess_format(bytes) {
+        Ok(ImageFormat::Gif) => true,
+        Ok(ImageFormat::Png) => PngDecoder::new(Cursor::new(bytes)).is_ok_and(|decoder| decoder.is_apng()),
+        Ok(ImageFormat::WebP) => WebPDecoder::new(Cursor::new(bytes)).is_ok_and(|decoder| decoder.has_animation()),
+        _ => false,
+    }
+}
+
+// None if the image couldn't be decoded. Frames are decoded one at a time and decoding stops
+// once they'd pass the cache budget, so a small file with a huge canvas or thousands of frames can't
+fn decode_frames(bytes: &[u8]) -> Option<Decoded> {
+    let (canvas, frames): ((u32, u32), Frames) = match image::guess_format(bytes).ok()? {
+        ImageFormat::Gif => {
+            let decoder = GifDecoder::new(Cursor::new(bytes)).ok()?;
+            (decoder.dimensions(), decoder.into_frames())
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(bytes)).ok()?;
+            (decoder.dimensions(), decoder.apng().into_frames())
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(bytes)).ok()?;
+            (decoder.dimensions(), decoder.into_frames())
+        }
+        _ => return None,
+    };
+    // every frame is composited onto the full canvas
+    if canvas.0 as usize * canvas.1 as usize * 4 > CACHE_BYTES {
+        return Some(Decoded::TooLarge);
+    }
+    let mut images = vec![];
+    let mut total = 0;
+    for frame in frames {
+        let frame = frame.ok()?;
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        // browsers bump very short delays up as well
+        let delay = (numerator as u64 / denominator.max(1) as u64).max(20);
+        let buffer = frame.into_buffer();
+        let size = [buffer.width() as usize, buffer.height() as usize];
+        total += size[0] * size[1] * 4;
+        if total > CACHE_BYTES {
+            return Some(Decoded::TooLarge);
+        }
+        images.push((ColorImage::from_rgba_unmultiplied(size, buffer.as_raw()), delay));
+    }
+    Some(Decoded::Frames(images, total))
+}
+
+fn current_frame<'a>(ctx: &Context, animation: &'a Animation) -> &'a TextureHandle {
+    let elapsed = (ctx.input(|input| input.time) * 1000.0) as u64 % animation.duration_ms.max(1);
+    let mut frame_end = 0;
+    for (texture, delay) in &animation.frames {
+        frame_end += delay;
+        if elapsed < frame_end {
+            ctx.request_repaint_after(Duration::from_millis(frame_end - elapsed));
+            return texture;
+        }
+    }
+    &animation.frames[0].0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let mut cache = Cache::default();
+        cache.insert("decoding".into(), Entry::Decoding, 0);
+        for frame in 1..=MAX_ENTRIES as u64 {
+            cache.insert(format!("still-{}", frame), Entry::Static, frame);
+        }
+        assert_eq!(cache.entries.len(), MAX_ENTRIES);
+        assert!(cache.entries.contains_key("decoding"));
+        assert!(!cache.entries.contains_key("still-1"));
+        assert!(cache.entries.contains_key("still-2"));
+    }
+
+    #[test]
+    fn huge_canvases_are_not_decoded() {
+        // a 65535x65535 screen with a single 1x1 frame
+        let mut gif = b"GIF89a\xff\xff\xff\xff\x00\x00\x00".to_vec();
+        gif.extend(b",\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00;");
+        assert!(matches!(decode_frames(&gif), Some(Decoded::TooLarge)));
+    }
+}
@@ -0,0 +1,203 @@
+use egui::{Color32, Id, RichText, Stroke, Ui, Vec2};
+use egui::ImageSource::Uri;
+use twilight_model::channel::Attachment;
+use crate::app::media::MediaGate;
+use crate::discord::util;
+
+const SINGLE_IMAGE_SIZE: f32 = 350.0;
+const GALLERY_TILE_SIZE: f32 = 170.0;
+const GALLERY_COLUMNS: usize = 3;
+const CARD_WIDTH: f32 = 350.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Image,
+    Video,
+    Audio,
+    File,
+}
+
+// The content type is missing on older attachments, the extension is the fallback
+fn kind(attachment: &Attachment) -> Kind {
+    let content_type = attachment.content_type.as_deref().unwrap_or("");
+    let link = util::strip_parameters(attachment.url.to_owned());
+    if content_type.starts_with("image/") || util::is_image_file(&link) {
+        Kind::Image
+    } else if content_type.starts_with("video/") || [".mp4", ".webm", ".mov"].iter().any(|ext| link.ends_with(ext)) {
+        Kind::Video
+    } else if content_type.starts_with("audio/") || [".mp3", ".ogg", ".wav", ".flac"].iter().any(|ext| link.ends_with(ext)) {
+        Kind::Audio
+    } else {
+        Kind::File
+    }
+}
+
+// Uploads marked as spoilers are renamed with this prefix
+fn is_spoiler(attachment: &Attachment) -> bool {
+    attachment.filename.starts_with("SPOILER_")
+}
+
+fn spoiler_id(attachment: &Attachment) -> Id {
+    Id::new(("spoiler", attachment.id))
+}
+
+pub enum AttachmentAction<'a> {
+    Download(&'a Attachment),
+    View(&'a Attachment),
+}
+
+// Images that can be shown inline and in the viewer
+pub fn is_viewable(attachment: &Attachment, gate: &MediaGate) -> bool {
+    kind(attachment) == Kind::Image && gate.allows(&attachment.url, attachment.content_type.as_deref())
+}
+
+// Images go into a gallery, everything else gets a card with its details
+pub fn show_attachments<'a>(ui: &mut Ui, attachments: &'a [Attachment], gate: &MediaGate) -> Option<AttachmentAction<'a>> {
+    let (images, others): (Vec<&Attachment>, Vec<&Attachment>) = attachments.iter()
+        .partition(|attachment| kind(attachment) == Kind::Image);
+    let (images, hidden): (Vec<&Attachment>, Vec<&Attachment>) = images.into_iter()
+        .partition(|image| is_viewable(image, gate));
+    for image in hidden {
+        let label = format!("🖼 {} ({})", image.filename, util::format_size(image.size));
+        gate.placeholder(ui, &image.url, image.content_type.as_deref(), &label);
+    }
+    let mut action = None;
+    match images.as_slice() {
+        [] => {}
+        [image] => {
+            let size = shown_size(image, Vec2::splat(SINGLE_IMAGE_SIZE));
+            let id = spoiler_id(image);
+            if gate.covered(ui, id, is_spoiler(image)) {
+                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+                gate.cover(ui, rect, &response, id);
+            } else {
+                let response = ui.add(egui::Image::new(Uri(thumbnail(ui, image, size).into()))
+                    .fit_to_exact_size(size)
+                    .rounding(5.0)
+                    .sense(egui::Sense::click()));
+                if response.on_hover_text(details(image)).clicked() {
+                    action = Some(AttachmentAction::View(image));
+                }
+            }
+        }
+        _ => {
+            if let Some(image) = gallery(ui, &images, gate) {
+                action = Some(AttachmentAction::View(image));
+            }
+        }
+    }
+    for attachment in others {
+        if file_card(ui, attachment) {
+            action = Some(AttachmentAction::Download(attachment));
+        }
+    }
+    action
+}
+
+// Returns the clicked image
+fn gallery<'a>(ui: &mut Ui, images: &[&'a Attachment], gate: &MediaGate) -> Option<&'a Attachment> {
+    let mut clicked = None;
+    egui::Grid::new(("gallery", images[0].id))
+        .spacing(Vec2::splat(4.0))
+        .show(ui, |ui| {
+            for (index, image) in images.iter().enumerate() {
+                let (rect, response) = ui.allocate_exact_size(Vec2::splat(GALLERY_TILE_SIZE), egui::Sense::click());
+                let id = spoiler_id(image);
+                if gate.covered(ui, id, is_spoiler(image)) {
+                    gate.cover(ui, rect, &response, id);
+                } else {
+                    if ui.is_rect_visible(rect) {
+                        ui.painter().rect_filled(rect, 5.0, ui.visuals().faint_bg_color);
+                        let size = shown_size(image, Vec2::splat(GALLERY_TILE_SIZE));
+                        egui::Image::new(Uri(thumbnail(ui, image, size).into()))
+                            .maintain_aspect_ratio(true)
+                            .rounding(5.0)
+                            .paint_at(ui, rect);
+                    }
+                    if response.on_hover_text(details(image)).clicked() {
+                        clicked = Some(*image);
+                    }
+                }
+                if (index + 1) % GALLERY_COLUMNS == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+    clicked
+}
+
+// Half the original size like before, bounded by `max`
+fn shown_size(image: &Attachment, max: Vec2) -> Vec2 {
+    let (Some(width), Some(height)) = (image.width, image.height) else {
+        return max;
+    };
+    let original = Vec2::new(width as f32, height as f32) * 0.5;
+    let scale = (max.x / original.x).min(max.y / original.y).min(1.0);
+    original * scale
+}
+
+// Only as many pixels as are shown are requested
+fn thumbnail(ui: &Ui, image: &Attachment, size: Vec2) -> String {
+    let pixels = size * ui.ctx().pixels_per_point();
+    util::thumbnail_url(&image.url, pixels.x.round() as u32, pixels.y.round() as u32)
+}
+
+fn details(attachment: &Attachment) -> String {
+    let mut details = format!("{}\n{}", attachment.filename, util::format_size(attachment.size));
+    if let Some(content_type) = &attachment.content_type {
+        details.push_str(&format!(" • {}", content_type));
+    }
+    if let (Some(width), Some(height)) = (attachment.width, attachment.height) {
+        details.push_str(&format!(" • {}x{}", width, height));
+    }
+    if let Some(description) = &attachment.description {
+        details.push_str(&format!("\n{}", description));
+    }
+    details
+}
+
+// Video and audio can't be played back here, they get a card like any other file
+fn file_card(ui: &mut Ui, attachment: &Attachment) -> bool {
+    let kind = kind(attachment);
+    let icon = match kind {
+        Kind::Image => "🖼",
+        Kind::Video => "🎞",
+        Kind::Audio => "🔊",
+        Kind::File => "📄",
+    };
+    let mut download = false;
+    egui::Frame::none()
+        .fill(ui.visuals().faint_bg_color)
+        .stroke(Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color))
+        .rounding(4.0)
+        .inner_margin(8.0)
+        .show(ui, |ui| {
+            ui.set_width(CARD_WIDTH);
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(icon).size(28.0));
+                ui.vertical(|ui| {
+                    ui.label(RichText::new(&attachment.filename).strong().color(ui.visuals().hyperlink_color));
+                    let mut info = vec![util::format_size(attachment.size)];
+                    if let Some(content_type) = &attachment.content_type {
+                        info.push(content_type.clone());
+                    }
+                    if let (Kind::Video, Some(width), Some(height)) = (kind, attachment.width, attachment.height) {
+                        info.push(format!("{}x{}", width, height));
+                    }
+                    if let Some(secs) = attachment.duration_secs {
+                        info.push(format!("{}:{:02}", secs as u64 / 60, secs as u64 % 60));
+                    }
+                    ui.label(RichText::new(info.join(" • ")).small().color(Color32::GRAY));
+                });
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    download = ui.button("Download").clicked();
+                    // the signed url is what opens, the hover only shows where it points
+                    let shown_url = util::strip_parameters(attachment.url.to_owned());
+                    if kind != Kind::File && ui.button("Open").on_hover_text(shown_url).clicked() {
+                        ui.ctx().open_url(egui::OpenUrl::new_tab(&attachment.url));
+                    }
+                });
+            });
+        });
+    download
+}
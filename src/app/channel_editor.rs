@@ -0,0 +1,394 @@
+use egui::{Context, Vec2};
+use twilight_model::channel::{Channel, ChannelType};
+use twilight_model::channel::permission_overwrite::PermissionOverwriteType as OverwriteKind;
+use twilight_model::guild::{Member, Permissions, Role};
+use twilight_model::http::permission_overwrite::PermissionOverwriteType;
+use twilight_validate::channel::{self as validate, CHANNEL_NAME_LENGTH_MAX, CHANNEL_TOPIC_LENGTH_MAX};
+use crate::app::DiscordApp;
+use crate::discord::guild::PERMISSION_NAMES;
+use crate::discord::jobs::{CreateChannel, DeleteChannel, DeletePermissionOverwrite, EditChannel, EditPermissionOverwrite, Job};
+
+pub struct ChannelEditor {
+    pub open: bool,
+    channel_id: u64, // 0 while creating a new channel
+    kind: ChannelType,
+    name: String,
+    topic: String,
+    slowmode: u16,
+    nsfw: bool,
+    bitrate: u32,
+    user_limit: u16,
+    parent_id: Option<u64>,
+
+    overwrites: Vec<OverwriteDraft>,
+    removed_overwrites: Vec<OverwriteDraft>,
+    selected_overwrite: usize,
+    new_target_id: String,
+    new_target_kind: PermissionOverwriteType,
+    error: String,
+}
+
+struct OverwriteDraft {
+    target_id: u64,
+    kind: PermissionOverwriteType,
+    allow: Permissions,
+    deny: Permissions,
+    changed: bool,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum PermissionState {
+    Allow,
+    Inherit,
+    Deny,
+}
+
+impl ChannelEditor {
+    pub fn new() -> Self {
+        Self::create(ChannelType::GuildText, None)
+    }
+
+    pub fn create(kind: ChannelType, parent_id: Option<u64>) -> Self {
+        Self{
+            open: false,
+            channel_id: 0,
+            kind,
+            name: "".into(),
+            topic: "".into(),
+            slowmode: 0,
+            nsfw: false,
+            bitrate: 64000,
+            user_limit: 0,
+            parent_id,
+            overwrites: vec![],
+            removed_overwrites: vec![],
+            selected_overwrite: 0,
+            new_target_id: "".into(),
+            new_target_kind: PermissionOverwriteType::Role,
+            error: "".into(),
+        }
+    }
+
+    pub fn edit(channel: &Channel) -> Self {
+        let mut overwrites = vec![];
+        for overwrite in channel.permission_overwrites.iter().flatten() {
+            let kind = match overwrite.kind {
+                OverwriteKind::Member => PermissionOverwriteType::Member,
+                _ => PermissionOverwriteType::Role,
+            };
+            overwrites.push(OverwriteDraft {
+                target_id: overwrite.id.get(),
+                kind,
+                allow: overwrite.allow,
+                deny: overwrite.deny,
+                changed: false,
+            });
+        }
+        Self{
+            open: true,
+            channel_id: channel.id.get(),
+            kind: channel.kind,
+            name: channel.name.clone().unwrap_or_default(),
+            topic: channel.topic.clone().unwrap_or_default(),
+            slowmode: channel.rate_limit_per_user.unwrap_or(0),
+            nsfw: channel.nsfw.unwrap_or(false),
+            bitrate: channel.bitrate.unwrap_or(64000),
+            user_limit: channel.user_limit.unwrap_or(0) as u16,
+            parent_id: channel.parent_id.map(|id| id.get()),
+            overwrites,
+            removed_overwrites: vec![],
+            selected_overwrite: 0,
+            new_target_id: "".into(),
+            new_target_kind: PermissionOverwriteType::Role,
+            error: "".into(),
+        }
+    }
+
+    fn is_creating(&self) -> bool {
+        self.channel_id == 0
+    }
+
+    // Turns the edited state into jobs, overwrites which weren't touched aren't sent
+    fn jobs(&mut self, server_id: u64) -> Result<Vec<Job>, String> {
+        validate::name(&self.name).map_err(|err| err.to_string())?;
+        if self.kind == ChannelType::GuildText && !self.is_creating() {
+            validate::topic(&self.topic).map_err(|err| err.to_string())?;
+        }
+        let mut jobs = vec![];
+        if self.is_creating() {
+            let parent_id = if self.kind == ChannelType::GuildCategory { None } else { self.parent_id };
+            let create = CreateChannel::new(server_id, self.name.clone(), self.kind, parent_id);
+            jobs.push(Job::CreateChannel(create));
+            return Ok(jobs);
+        }
+        let mut edit = EditChannel::new(self.channel_id, self.name.clone());
+        match self.kind {
+            ChannelType::GuildText => {
+                edit.topic = Some(self.topic.clone());
+                edit.rate_limit_per_user = Some(self.slowmode);
+                edit.nsfw = Some(self.nsfw);
+                edit.parent_id = self.parent_id;
+            }
+            ChannelType::GuildVoice => {
+                edit.bitrate = Some(self.bitrate);
+                edit.user_limit = Some(self.user_limit);
+                edit.nsfw = Some(self.nsfw);
+                edit.parent_id = self.parent_id;
+            }
+            _ => {}
+        }
+        jobs.push(Job::EditChannel(edit));
+        for draft in self.overwrites.iter_mut().filter(|draft| draft.changed) {
+            let overwrite = EditPermissionOverwrite::new(self.channel_id, draft.target_id, draft.kind, draft.allow, draft.deny);
+            jobs.push(Job::EditPermissionOverwrite(overwrite));
+            draft.changed = false;
+        }
+        for draft in self.removed_overwrites.drain(..) {
+            let overwrite = DeletePermissionOverwrite::new(self.channel_id, draft.target_id, draft.kind);
+            jobs.push(Job::DeletePermissionOverwrite(overwrite));
+        }
+        Ok(jobs)
+    }
+}
+
+impl DiscordApp {
+    pub fn channel_editor_window(&mut self, ctx: &Context) {
+        self.show_channel_error();
+        if !self.channel_editor.open {
+            return;
+        }
+        let title = if self.channel_editor.is_creating() { "Create channel" } else { "Edit channel" };
+        let mut open = true;
+        let mut close = false;
+        let mut jobs = vec![];
+        egui::Window::new(title)
+            .id(egui::Id::new("channel_editor"))
+            .open(&mut open)
+            .default_size(Vec2::new(300.0, 400.0))
+            .resizable(true)
+            .show(ctx, |ui| {
+                let editor = &mut self.channel_editor;
+                let categories = self.cache.categories.get();
+                egui::Grid::new("channel_settings").num_columns(2).show(ui, |ui| {
+                    ui.label("Name");
+                    ui.add(egui::TextEdit::singleline(&mut editor.name).char_limit(CHANNEL_NAME_LENGTH_MAX));
+                    ui.end_row();
+                    if editor.is_creating() {
+                        ui.label("Type");
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(&mut editor.kind, ChannelType::GuildText, "Text");
+                            ui.selectable_value(&mut editor.kind, ChannelType::GuildVoice, "Voice");
+                            ui.selectable_value(&mut editor.kind, ChannelType::GuildCategory, "Category");
+                        });
+                        ui.end_row();
+                    }
+                    if editor.kind == ChannelType::GuildText && !editor.is_creating() {
+                        ui.label("Topic");
+                        ui.add(egui::TextEdit::multiline(&mut editor.topic).char_limit(CHANNEL_TOPIC_LENGTH_MAX));
+                        ui.end_row();
+                        ui.label("Slowmode");
+                        ui.add(egui::DragValue::new(&mut editor.slowmode).clamp_range(0..=21600).suffix(" s"));
+                        ui.end_row();
+                    }
+                    if editor.kind == ChannelType::GuildVoice && !editor.is_creating() {
+                        ui.label("Bitrate");
+                        ui.add(egui::DragValue::new(&mut editor.bitrate).clamp_range(8000..=384000).speed(1000));
+                        ui.end_row();
+                        ui.label("User limit");
+                        ui.add(egui::DragValue::new(&mut editor.user_limit).clamp_range(0..=99));
+                        ui.end_row();
+                    }
+                    if editor.kind != ChannelType::GuildCategory && !editor.is_creating() {
+                        ui.label("NSFW");
+                        ui.checkbox(&mut editor.nsfw, "");
+                        ui.end_row();
+                    }
+                    if editor.kind != ChannelType::GuildCategory {
+                        ui.label("Category");
+                        let selected = categories.iter()
+                            .find(|category| Some(category.id.get()) == editor.parent_id)
+                            .and_then(|category| category.name.clone())
+                            .unwrap_or("No category".into());
+                        egui::ComboBox::from_id_source("channel_category")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut editor.parent_id, None, "No category");
+                                for category in categories.iter() {
+                                    let name = category.name.clone().unwrap_or_default();
+                                    ui.selectable_value(&mut editor.parent_id, Some(category.id.get()), name);
+                                }
+                            });
+                        ui.end_row();
+                    }
+                });
+                drop(categories);
+
+                if !editor.is_creating() {
+                    ui.separator();
+                    let servers = self.cache.servers.get();
+                    let roles = servers.iter()
+                        .find(|server| server.id == self.selected_server_id)
+                        .map(|server| server.roles.as_slice())
+                        .unwrap_or_default();
+                    let members = self.cache.members.get();
+                    overwrites_editor(ui, editor, roles, &members);
+                }
+
+                ui.separator();
+                if !editor.error.is_empty() {
+                    ui.colored_label(ui.visuals().error_fg_color, &editor.error);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() && self.selected_server_id != 0 {
+                        match editor.jobs(self.selected_server_id) {
+                            Ok(edited) => {
+                                jobs = edited;
+                                close = true;
+                            }
+                            Err(err) => editor.error = err,
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                    if !editor.is_creating() && ui.button("Delete channel").clicked() {
+                        jobs.push(Job::DeleteChannel(DeleteChannel::new(editor.channel_id)));
+                        close = true;
+                    }
+                });
+            });
+        for job in jobs {
+            self.append_job(job);
+        }
+        if !open || close {
+            self.channel_editor.open = false;
+        }
+    }
+
+    // Reopens the edited channel, with the changes still in place if it's the one last edited
+    fn show_channel_error(&mut self) {
+        if self.shared_cache.channel_error.load().is_none() {
+            return;
+        }
+        let Some((channel_id, err)) = self.shared_cache.channel_error.update(Option::take) else {
+            return;
+        };
+        if self.channel_editor.channel_id != channel_id {
+            let channels = self.cache.channels.get();
+            let Some(channel) = channels.0.iter().chain(channels.1.iter()).find(|channel| channel.id.get() == channel_id) else {
+                return;
+            };
+            self.channel_editor = ChannelEditor::edit(channel);
+        }
+        self.channel_editor.open = true;
+        self.channel_editor.error = format!("Couldn't save the channel: {}", err);
+    }
+}
+
+fn overwrites_editor(ui: &mut egui::Ui, editor: &mut ChannelEditor, roles: &[Role], members: &[Member]) {
+    ui.heading("Permission overwrites");
+    let mut removed = None;
+    for (i, draft) in editor.overwrites.iter().enumerate() {
+        ui.horizontal(|ui| {
+            let label = overwrite_label(draft, roles, members);
+            if ui.selectable_label(i == editor.selected_overwrite, label).clicked() {
+                editor.selected_overwrite = i;
+            }
+            if ui.small_button("x").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    if let Some(index) = removed {
+        let draft = editor.overwrites.remove(index);
+        editor.removed_overwrites.push(draft);
+        editor.selected_overwrite = 0;
+    }
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut editor.new_target_id)
+            .desired_width(140.0)
+            .hint_text("Role or member ID"));
+        ui.selectable_value(&mut editor.new_target_kind, PermissionOverwriteType::Role, "Role");
+        ui.selectable_value(&mut editor.new_target_kind, PermissionOverwriteType::Member, "Member");
+        if ui.button("Add").clicked() {
+            if let Ok(target_id) = editor.new_target_id.trim().parse::<u64>() {
+                let exists = editor.overwrites.iter().any(|draft| draft.target_id == target_id);
+                if target_id != 0 && !exists {
+                    // added back after being removed, the new overwrite replaces it instead
+                    editor.removed_overwrites.retain(|draft| draft.target_id != target_id);
+                    editor.overwrites.push(OverwriteDraft {
+                        target_id,
+                        kind: editor.new_target_kind,
+                        allow: Permissions::empty(),
+                        deny: Permissions::empty(),
+                        changed: true,
+                    });
+                    editor.selected_overwrite = editor.overwrites.len() - 1;
+                    editor.new_target_id.clear();
+                }
+            }
+        }
+    });
+
+    let Some(draft) = editor.overwrites.get_mut(editor.selected_overwrite) else {
+        return;
+    };
+    egui::ScrollArea::vertical().max_height(250.0).show(ui, |ui| {
+        egui::Grid::new("overwrite_grid").striped(true).num_columns(4).show(ui, |ui| {
+            for (name, permission) in PERMISSION_NAMES {
+                let before = if draft.allow.contains(permission) {
+                    PermissionState::Allow
+                } else if draft.deny.contains(permission) {
+                    PermissionState::Deny
+                } else {
+                    PermissionState::Inherit
+                };
+                let mut state = before;
+                ui.label(name);
+                ui.selectable_value(&mut state, PermissionState::Allow, "✔");
+                ui.selectable_value(&mut state, PermissionState::Inherit, "/");
+                ui.selectable_value(&mut state, PermissionState::Deny, "✖");
+                ui.end_row();
+                if state != before {
+                    draft.allow.set(permission, state == PermissionState::Allow);
+                    draft.deny.set(permission, state == PermissionState::Deny);
+                    draft.changed = true;
+                }
+            }
+        });
+    });
+}
+
+fn overwrite_label(draft: &OverwriteDraft, roles: &[Role], members: &[Member]) -> String {
+    match draft.kind {
+        PermissionOverwriteType::Member => {
+            let member = members.iter().find(|member| member.user.id == draft.target_id);
+            match member {
+                Some(member) => member.user.name.clone(),
+                None => format!("Member {}", draft.target_id),
+            }
+        }
+        _ => {
+            let role = roles.iter().find(|role| role.id == draft.target_id);
+            match role {
+                Some(role) => role.name.clone(),
+                None => format!("Role {}", draft.target_id),
+            }
+        }
+    }
+}
+
+// Moves the channel within its list, returns false if it isn't in this list
+pub fn move_channel(channels: &mut Vec<Channel>, channel_id: u64, target: usize) -> bool {
+    let Some(from) = channels.iter().position(|channel| channel.id == channel_id) else {
+        return false;
+    };
+    // the target is the row it's dropped above, which moves up once the channel is taken out
+    let target = if target > from { target - 1 } else { target };
+    if from == target {
+        return false;
+    }
+    let channel = channels.remove(from);
+    channels.insert(target.min(channels.len()), channel);
+    true
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::SystemTime;
+use egui::Context;
+use egui::load::{Bytes, BytesLoader, BytesLoadResult, BytesPoll, LoadError};
+use crate::discord::shared_cache::ArcMutex;
+use crate::discord::util;
+
+// Replaces the http loader of egui_extras for remote images.
+// Downloads are kept on disk, keyed by the url without its parameters (the requested size excepted)
+// so expiring signatures don't invalidate them. The least recently used files are evicted
+// once the directory grows past its limit, the file modification time marks the last use.
+
+#[derive(Clone)]
+enum Entry {
+    Pending,
+    Ready(Bytes),
+    Failed(String),
+}
+
+struct CachedFile {
+    size: u64,
+    used: SystemTime,
+}
+
+pub struct DiskCache {
+    directory: PathBuf,
+    max_bytes: u64,
+    entries: ArcMutex<HashMap<String, Entry>>, // by uri, what's been loaded this session
+    files: ArcMutex<HashMap<PathBuf, CachedFile>>,
+}
+
+impl DiskCache {
+    pub const ID: &'static str = egui::generate_loader_id!(DiskCache);
+
+    pub fn new(directory: PathBuf, max_bytes: u64) -> Self {
+        let _ = fs::create_dir_all(&directory);
+        let mut files = HashMap::new();
+        if let Ok(dir) = fs::read_dir(&directory) {
+            for file in dir.flatten() {
+                let Ok(metadata) = file.metadata() else {
+                    continue;
+                };
+                let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.insert(file.path(), CachedFile { size: metadata.len(), used });
+            }
+        }
+        Self{
+            directory,
+            max_bytes,
+            entries: ArcMutex::new(HashMap::new()),
+            files: ArcMutex::new(files),
+        }
+    }
+
+    // Thumbnails of the same image at different sizes are separate files
+    fn file_path(&self, uri: &str) -> PathBuf {
+        let mut key = util::strip_parameters(uri.to_string());
+        if let Some((_, query)) = uri.split_once('?') {
+            let sizes: Vec<&str> = query.split('&')
+                .filter(|param| param.starts_with("width=") || param.starts_with("height=") || param.starts_with("size="))
+                .collect();
+            if !sizes.is_empty() {
+                key = format!("{}?{}", key, sizes.join("&"));
+            }
+        }
+        // a checksum rather than std's hasher, whose output may change between Rust releases
+        self.directory.join(format!("{:08x}-{:x}", crc32fast::hash(key.as_bytes()), key.len()))
+    }
+
+    fn fetch(&self, ctx: Context, uri: String) {
+        let path = self.file_path(&uri);
+        let entries = self.entries.clone();
+        let files = self.files.clone();
+        let max_bytes = self.max_bytes;
+        thread::spawn(move || {
+            let entry = match read_cached(&path, &files) {
+                Some(bytes) => Entry::Ready(bytes.into()),
+                None => match download(&uri) {
+                    Ok(bytes) => {
+                        store(&path, &bytes, &files, max_bytes);
+                        Entry::Ready(bytes.into())
+                    }
+                    Err(err) => Entry::Failed(err),
+                },
+            };
+            entries.guard().insert(uri, entry);
+            ctx.request_repaint();
+        });
+    }
+}
+
+impl BytesLoader for DiskCache {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn load(&self, ctx: &Context, uri: &str) -> BytesLoadResult {
+        if !uri.starts_with("https://") && !uri.starts_with("http://") {
+            return Err(LoadError::NotSupported);
+        }
+        let mut entries = self.entries.guard();
+        match entries.get(uri).cloned() {
+            Some(Entry::Ready(bytes)) => Ok(BytesPoll::Ready { size: None, bytes, mime: None }),
+            Some(Entry::Pending) => Ok(BytesPoll::Pending { size: None }),
+            Some(Entry::Failed(err)) => Err(LoadError::Loading(err)),
+            None => {
+                entries.insert(uri.to_string(), Entry::Pending);
+                drop(entries);
+                self.fetch(ctx.clone(), uri.to_string());
+                Ok(BytesPoll::Pending { size: None })
+            }
+        }
+    }
+
+    // Only the memory is freed, the file stays on disk
+    fn forget(&self, uri: &str) {
+        self.entries.guard().remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.entries.guard().clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.entries.guard().values()
+            .map(|entry| match entry {
+                Entry::Ready(bytes) => bytes.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+pub fn cache_directory() -> PathBuf {
+    dirs_next::cache_dir()
+        .map(|dir| dir.join("discord-bot-gui").join("images"))
+        .unwrap_or(PathBuf::from("res/cache/images"))
+}
+
+fn read_cached(path: &PathBuf, files: &ArcMutex<HashMap<PathBuf, CachedFile>>) -> Option<Vec<u8>> {
+    let bytes = fs::read(path).ok()?;
+    let now = SystemTime::now();
+    // touched so the order survives restarts
+    if let Ok(file) = File::options().append(true).open(path) {
+        let _ = file.set_modified(now);
+    }
+    if let Some(cached) = files.guard().get_mut(path) {
+        cached.used = now;
+    }
+    Some(bytes)
+}
+
+fn download(uri: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(uri).call().map_err(|err| err.to_string())?;
+    let mut bytes = vec![];
+    response.into_reader().read_to_end(&mut bytes).map_err(|err| err.to_string())?;
+    Ok(bytes)
+}
+
+fn store(path: &PathBuf, bytes: &[u8], files: &ArcMutex<HashMap<PathBuf, CachedFile>>, max_bytes: u64) {
+    // it would push out everything else and still not fit, it's only kept in memory
+    if bytes.len() as u64 > max_bytes {
+        return;
+    }
+    if fs::write(path, bytes).is_err() {
+        return;
+    }
+    let mut files = files.guard();
+    files.insert(path.clone(), CachedFile { size: bytes.len() as u64, used: SystemTime::now() });
+    let mut total: u64 = files.values().map(|file| file.size).sum();
+    if total <= max_bytes {
+        return;
+    }
+    let mut oldest: Vec<(PathBuf, u64, SystemTime)> = files.iter()
+        .map(|(path, file)| (path.clone(), file.size, file.used))
+        .collect();
+    oldest.sort_by_key(|(_, _, used)| *used);
+    for (old_path, size, _) in oldest {
+        if total <= max_bytes {
+            break;
+        }
+        if &old_path == path {
+            continue;
+        }
+        if fs::remove_file(&old_path).is_ok() {
+            files.remove(&old_path);
+            total -= size;
+        }
+    }
+}
+
+// Registered last so it's asked before the http loader
+pub fn install(ctx: &Context, max_bytes: u64) {
+    ctx.add_bytes_loader(Arc::new(DiskCache::new(cache_directory(), max_bytes)));
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+use egui::{Context, RichText};
+use twilight_model::channel::Attachment;
+use crate::app::DiscordApp;
+use crate::config::CONFIG_PATH;
+use crate::discord::download::{self, DownloadProgress, DownloadState};
+use crate::discord::jobs::{Download, Job};
+use crate::discord::util;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct Downloads {
+    pub open: bool,
+    status: String,
+}
+
+impl Downloads {
+    pub fn new() -> Self {
+        Self{
+            open: false,
+            status: "".into(),
+        }
+    }
+}
+
+impl DiscordApp {
+    // The signed url is needed, the CDN refuses attachments without its parameters
+    pub fn download_attachments<'a>(&mut self, attachments: impl IntoIterator<Item = &'a Attachment>) {
+        let directory = download::download_directory(&self.config.download_dir);
+        for attachment in attachments {
+            let filename = download::safe_filename(&attachment.filename, attachment.id.get());
+            let job = Download::new(attachment.url.clone(), filename, attachment.size, directory.clone());
+            self.append_job(Job::Download(job));
+        }
+        self.downloads.open = true;
+    }
+
+    pub fn downloads_window(&mut self, ctx: &Context) {
+        let mut open = self.downloads.open;
+        egui::Window::new("Downloads")
+            .id(egui::Id::new("downloads_window"))
+            .open(&mut open)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Directory");
+                    ui.add(egui::TextEdit::singleline(&mut self.config.download_dir)
+                        .hint_text("System downloads folder"));
+                    if ui.button("Save").clicked() {
+                        self.downloads.status = match self.config.save_config(CONFIG_PATH) {
+                            Ok(_) => "Saved".into(),
+                            Err(err) => format!("Couldn't save config: {}", err),
+                        };
+                    }
+                });
+                let directory = download::download_directory(&self.config.download_dir);
+                ui.weak(format!("Saving to {}", directory.display()));
+                if !self.downloads.status.is_empty() {
+                    ui.small(&self.downloads.status);
+                }
+                ui.separator();
+                // copied so the download threads aren't kept waiting while this is drawn
+                let downloads: Vec<DownloadProgress> = self.shared_cache.downloads.guard().iter()
+                    .map(|progress| progress.guard().clone())
+                    .collect();
+                if downloads.is_empty() {
+                    ui.weak("Nothing downloaded yet");
+                }
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for progress in &downloads {
+                        ui.label(RichText::new(&progress.filename).strong());
+                        match &progress.state {
+                            DownloadState::Running => {
+                                let text = format!("{} / {}", util::format_size(progress.received), util::format_size(progress.size));
+                                let fraction = if progress.size == 0 { 0.0 } else { progress.received as f32 / progress.size as f32 };
+                                ui.add(egui::ProgressBar::new(fraction).text(text));
+                                // the transfer doesn't wake the UI on its own
+                                ui.ctx().request_repaint_after(PROGRESS_INTERVAL);
+                            }
+                            DownloadState::Done(checksum) => {
+                                ui.small(format!("Saved to {} • crc32 {:08x}", progress.path.display(), checksum));
+                            }
+                            DownloadState::Failed(err) => {
+                                ui.colored_label(ui.visuals().error_fg_color, err);
+                            }
+                        }
+                        ui.add_space(4.0);
+                    }
+                });
+                ui.separator();
+                if ui.button("Clear finished").clicked() {
+                    self.shared_cache.downloads.guard().retain(|progress| matches!(progress.guard().state, DownloadState::Running));
+                }
+            });
+        self.downloads.open = open;
+    }
+}
@@ -0,0 +1,165 @@
+use egui::{Color32, Id, RichText, Sense, Stroke, Ui, Vec2};
+use egui::ImageSource::Uri;
+use twilight_model::channel::message::Embed;
+use twilight_model::channel::message::embed::EmbedField;
+use crate::app::markdown::{self, Clicked, Link};
+use crate::app::media::MediaGate;
+use crate::app::mentions::MentionContext;
+use crate::discord::util;
+
+const EMBED_WIDTH: f32 = 400.0;
+const THUMBNAIL_SIZE: f32 = 80.0;
+const ICON_SIZE: f32 = 20.0;
+const INLINE_COLUMNS: usize = 3;
+
+pub fn has_images(embed: &Embed) -> bool {
+    embed.image.is_some() || embed.thumbnail.is_some()
+}
+
+// The media proxy url is preferred, the media policy may trust it when the original host isn't
+fn media_link(url: &str, proxy_url: Option<&String>) -> String {
+    util::strip_parameters(proxy_url.map_or(url, |proxy| proxy.as_str()).to_string())
+}
+
+// Returns the mention or link clicked in the embed
+pub fn show_embed(ui: &mut Ui, embed: &Embed, gate: &MediaGate, context: &MentionContext) -> Option<Clicked> {
+    let bar_color = embed.color.and_then(util::role_color).unwrap_or(ui.visuals().widgets.noninteractive.bg_stroke.color);
+    let mut clicked = None;
+    let frame = egui::Frame::none()
+        .fill(ui.visuals().faint_bg_color)
+        .stroke(Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color))
+        .rounding(4.0)
+        .inner_margin(egui::Margin { left: 12.0, right: 8.0, top: 8.0, bottom: 8.0 });
+    let response = frame.show(ui, |ui| {
+        ui.set_max_width(EMBED_WIDTH);
+        ui.horizontal_top(|ui| {
+            let thumbnail = embed.thumbnail.as_ref()
+                .map(|thumbnail| media_link(&thumbnail.url, thumbnail.proxy_url.as_ref()));
+            let text_width = if thumbnail.is_some() { EMBED_WIDTH - THUMBNAIL_SIZE - 8.0 } else { EMBED_WIDTH };
+            ui.vertical(|ui| {
+                ui.set_max_width(text_width);
+                clicked = embed_body(ui, embed, gate, context);
+            });
+            match thumbnail {
+                Some(thumbnail) if gate.allows(&thumbnail, None) => {
+                    let id = Id::new(("spoiler", &thumbnail));
+                    if gate.covered(ui, id, false) {
+                        let (rect, response) = ui.allocate_exact_size(Vec2::splat(THUMBNAIL_SIZE), Sense::click());
+                        gate.cover(ui, rect, &response, id);
+                    } else {
+                        ui.add(egui::Image::new(Uri(thumbnail.into()))
+                            .max_size(Vec2::splat(THUMBNAIL_SIZE))
+                            .rounding(4.0));
+                    }
+                }
+                Some(thumbnail) => gate.placeholder(ui, &thumbnail, None, "thumbnail"),
+                None => {}
+            }
+        });
+    }).response;
+    let bar = egui::Rect::from_min_size(response.rect.min, Vec2::new(4.0, response.rect.height()));
+    ui.painter().rect_filled(bar, egui::Rounding { nw: 4.0, sw: 4.0, ne: 0.0, se: 0.0 }, bar_color);
+    clicked
+}
+
+fn embed_body(ui: &mut Ui, embed: &Embed, gate: &MediaGate, context: &MentionContext) -> Option<Clicked> {
+    let mut clicked = None;
+    if let Some(author) = &embed.author {
+        ui.horizontal(|ui| {
+            let icon = author.icon_url.as_ref()
+                .map(|icon| media_link(icon, author.proxy_icon_url.as_ref()));
+            if let Some(icon) = icon.filter(|icon| gate.allows(icon, None)) {
+                ui.add(egui::Image::new(Uri(icon.into()))
+                    .fit_to_exact_size(Vec2::splat(ICON_SIZE))
+                    .rounding(ICON_SIZE / 2.0));
+            }
+            let name = RichText::new(&author.name).small().strong();
+            if let Some(url) = &author.url {
+                let name = ui.add(egui::Label::new(name).sense(egui::Sense::click()));
+                if name.on_hover_text(url).clicked() {
+                    clicked = Some(Clicked::Link(Link { text: author.name.clone(), url: url.clone() }));
+                }
+            } else {
+                ui.label(name);
+            }
+        });
+    }
+    if let Some(title) = &embed.title {
+        let mut text = RichText::new(title).strong();
+        if embed.url.is_some() {
+            text = text.color(ui.visuals().hyperlink_color);
+        }
+        let label = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+        if let Some(url) = &embed.url {
+            if label.on_hover_text(url).clicked() {
+                clicked = Some(Clicked::Link(Link { text: title.clone(), url: url.clone() }));
+            }
+        }
+    }
+    if let Some(description) = &embed.description {
+        let layout = markdown::layout_job(ui.ctx(), ui.style(), description, context);
+        if let Some(click) = markdown::show(ui, layout, gate.reveal_mode()).1 {
+            clicked = Some(click);
+        }
+    }
+    for row in field_rows(&embed.fields) {
+        ui.columns(row.len(), |columns| {
+            for (column, field) in columns.iter_mut().zip(row) {
+                column.label(RichText::new(&field.name).small().strong());
+                let layout = markdown::layout_job(column.ctx(), column.style(), &field.value, context);
+                if let Some(click) = markdown::show(column, layout, gate.reveal_mode()).1 {
+                    clicked = Some(click);
+                }
+            }
+        });
+    }
+    let image = embed.image.as_ref()
+        .map(|image| media_link(&image.url, image.proxy_url.as_ref()));
+    match image {
+        Some(image) if gate.allows(&image, None) => {
+            let id = Id::new(("spoiler", &image));
+            if gate.covered(ui, id, false) {
+                let (rect, response) = ui.allocate_exact_size(Vec2::new(EMBED_WIDTH, 200.0), Sense::click());
+                gate.cover(ui, rect, &response, id);
+            } else {
+                ui.add(egui::Image::new(Uri(image.into()))
+                    .max_size(Vec2::new(EMBED_WIDTH, 300.0))
+                    .rounding(4.0));
+            }
+        }
+        Some(image) => gate.placeholder(ui, &image, None, "image"),
+        None => {}
+    }
+    if embed.footer.is_some() || embed.timestamp.is_some() {
+        ui.horizontal_wrapped(|ui| {
+            let mut footer = vec![];
+            if let Some(embed_footer) = &embed.footer {
+                let icon = embed_footer.icon_url.as_ref()
+                    .map(|icon| media_link(icon, embed_footer.proxy_icon_url.as_ref()));
+                if let Some(icon) = icon.filter(|icon| gate.allows(icon, None)) {
+                    ui.add(egui::Image::new(Uri(icon.into()))
+                        .fit_to_exact_size(Vec2::splat(ICON_SIZE * 0.8))
+                        .rounding(ICON_SIZE * 0.4));
+                }
+                footer.push(embed_footer.text.clone());
+            }
+            if let Some(timestamp) = embed.timestamp {
+                footer.push(context.time.datetime(timestamp.as_secs()));
+            }
+            ui.label(RichText::new(footer.join(" • ")).small().color(Color32::GRAY));
+        });
+    }
+    clicked
+}
+
+// Consecutive inline fields share a row, up to three per row
+fn field_rows(fields: &[EmbedField]) -> Vec<Vec<&EmbedField>> {
+    let mut rows: Vec<Vec<&EmbedField>> = vec![];
+    for field in fields {
+        match rows.last_mut() {
+            Some(row) if field.inline && row.len() < INLINE_COLUMNS && row.iter().all(|field| field.inline) => row.push(field),
+            _ => rows.push(vec![field]),
+        }
+    }
+    rows
+}
@@ -0,0 +1,205 @@
+use std::thread;
+use egui::{Context, Key, Rect, Sense, TextureOptions, Vec2};
+use egui::load::{BytesPoll, SizeHint, TexturePoll};
+use twilight_model::channel::Attachment;
+use crate::app::{animation, attachments};
+use crate::app::DiscordApp;
+use crate::app::media::MediaGate;
+use crate::discord::shared_cache::ArcMutex;
+use crate::discord::util;
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 16.0;
+
+pub struct Lightbox {
+    pub attachment: Option<Attachment>, // None while closed
+    zoom: f32,
+    offset: Vec2, // from the center of the view
+    fit: bool,
+    status: ArcMutex<String>, // set by the copy thread once it's done
+    clipboard: ArcMutex<Option<arboard::Clipboard>>, // the owner of the last copied image
+}
+
+impl Lightbox {
+    pub fn new() -> Self {
+        Self{
+            attachment: None,
+            zoom: 1.0,
+            offset: Vec2::ZERO,
+            fit: true,
+            status: ArcMutex::new("".into()),
+            clipboard: ArcMutex::new(None),
+        }
+    }
+
+    pub fn open(&mut self, attachment: Attachment) {
+        self.attachment = Some(attachment);
+        self.offset = Vec2::ZERO;
+        self.fit = true;
+        self.status.guard().clear();
+    }
+
+    fn copy_image(&self, ctx: &Context, url: &str) {
+        let Ok(BytesPoll::Ready { bytes, .. }) = ctx.try_load_bytes(url) else {
+            *self.status.guard() = "Image isn't loaded yet".into();
+            return;
+        };
+        *self.status.guard() = "Copying...".into();
+        let bytes = bytes.to_vec();
+        let status = self.status.clone();
+        let clipboard = self.clipboard.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            *status.guard() = match set_clipboard_image(&bytes, &clipboard) {
+                Ok(()) => "Copied".into(),
+                Err(err) => format!("Couldn't copy: {}", err),
+            };
+            ctx.request_repaint();
+        });
+    }
+}
+
+impl DiscordApp {
+    pub fn lightbox_window(&mut self, ctx: &Context) {
+        let Some(attachment) = self.lightbox.attachment.clone() else {
+            return;
+        };
+        let mut open = true;
+        let mut step = 0;
+        let mut save = false;
+        egui::Window::new(&attachment.filename)
+            .id(egui::Id::new("lightbox_window"))
+            .open(&mut open)
+            .default_size(Vec2::new(800.0, 600.0))
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let lightbox = &mut self.lightbox;
+                ui.horizontal(|ui| {
+                    if ui.button("◀").on_hover_text("Previous image").clicked() {
+                        step = -1;
+                    }
+                    if ui.button("▶").on_hover_text("Next image").clicked() {
+                        step = 1;
+                    }
+                    ui.separator();
+                    if ui.selectable_label(lightbox.fit, "Fit").clicked() {
+                        lightbox.fit = true;
+                        lightbox.offset = Vec2::ZERO;
+                    }
+                    if ui.button("1:1").clicked() {
+                        lightbox.fit = false;
+                        lightbox.zoom = 1.0;
+                        lightbox.offset = Vec2::ZERO;
+                    }
+                    ui.label(format!("{:.0}%", lightbox.zoom * 100.0));
+                    ui.separator();
+                    if ui.button("Copy").clicked() {
+                        lightbox.copy_image(ui.ctx(), &attachment.url);
+                    }
+                    save = ui.button("Save").clicked();
+                    ui.weak(util::format_size(attachment.size));
+                    let status = lightbox.status.guard();
+                    if !status.is_empty() {
+                        ui.small(&*status);
+                    }
+                });
+                ui.separator();
+                let (view, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
+                let Some(size) = image_size(ui.ctx(), &attachment.url) else {
+                    ui.put(view, egui::Spinner::new());
+                    return;
+                };
+                let fit_zoom = (view.width() / size.x).min(view.height() / size.y).min(1.0);
+                if lightbox.fit {
+                    lightbox.zoom = fit_zoom;
+                }
+                if response.hovered() {
+                    let scroll = ui.input(|i| i.scroll_delta.y);
+                    let pinch = ui.input(|i| i.zoom_delta());
+                    let factor = pinch * (scroll / 200.0).exp();
+                    if factor != 1.0 {
+                        // zoom around the pointer
+                        let pointer = ui.input(|i| i.pointer.hover_pos()).unwrap_or(view.center());
+                        let anchor = pointer - view.center() - lightbox.offset;
+                        let zoom = (lightbox.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+                        lightbox.offset -= anchor * (zoom / lightbox.zoom - 1.0);
+                        lightbox.zoom = zoom;
+                        lightbox.fit = false;
+                    }
+                }
+                if response.dragged() {
+                    lightbox.offset += response.drag_delta();
+                    lightbox.fit = false;
+                }
+                if response.double_clicked() {
+                    lightbox.fit = !lightbox.fit;
+                    lightbox.offset = Vec2::ZERO;
+                }
+                let rect = Rect::from_center_size(view.center() + lightbox.offset, size * lightbox.zoom);
+                let mut canvas = ui.child_ui(view, *ui.layout());
+                canvas.set_clip_rect(view);
+                animation::paint_at(&mut canvas, &attachment.url, rect);
+            });
+        if !ctx.wants_keyboard_input() {
+            ctx.input(|i| {
+                if i.key_pressed(Key::ArrowLeft) {
+                    step = -1;
+                } else if i.key_pressed(Key::ArrowRight) {
+                    step = 1;
+                } else if i.key_pressed(Key::Escape) {
+                    open = false;
+                }
+            });
+        }
+        if save {
+            self.download_attachments([&attachment]);
+        }
+        if step != 0 {
+            if let Some(next) = self.adjacent_image(&attachment, step) {
+                self.lightbox.open(next);
+            }
+        }
+        if !open {
+            self.lightbox.attachment = None;
+        }
+    }
+
+    // Images of the loaded messages, oldest first
+    fn adjacent_image(&self, current: &Attachment, step: isize) -> Option<Attachment> {
+        let messages = self.cache.messages.get();
+        let gate = MediaGate::new(&self.config.media, &self.shared_cache.loaded_media);
+        let images: Vec<&Attachment> = messages.iter().rev()
+            .flat_map(|msg| msg.attachments.iter())
+            .filter(|attachment| attachments::is_viewable(attachment, &gate))
+            .collect();
+        let index = images.iter().position(|image| image.id == current.id)?;
+        let next = index.checked_add_signed(step)?;
+        images.get(next).map(|image| (*image).clone())
+    }
+}
+
+fn image_size(ctx: &Context, url: &str) -> Option<Vec2> {
+    if let Some(size) = animation::frame_size(ctx, url) {
+        return Some(size);
+    }
+    match ctx.try_load_texture(url, TextureOptions::default(), SizeHint::default()) {
+        Ok(TexturePoll::Ready { texture }) => Some(texture.size),
+        _ => None,
+    }
+}
+
+// The first frame of animated images, decoded off the UI thread
+fn set_clipboard_image(bytes: &[u8], kept: &ArcMutex<Option<arboard::Clipboard>>) -> Result<(), String> {
+    let image = image::load_from_memory(bytes).map_err(|err| err.to_string())?.to_rgba8();
+    let data = arboard::ImageData {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        bytes: image.into_raw().into(),
+    };
+    let mut clipboard = arboard::Clipboard::new().map_err(|err| err.to_string())?;
+    clipboard.set_image(data).map_err(|err| err.to_string())?;
+    // on Linux the clipboard is served by its owner, so it's kept until the next copy
+    *kept.guard() = Some(clipboard);
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use std::io;
+use std::process::{Command, Stdio};
+use std::thread;
+use egui::{Context, RichText, Ui};
+use url::Url;
+use crate::app::DiscordApp;
+use crate::app::markdown::{self, Link};
+use crate::config::CONFIG_PATH;
+use crate::media_policy;
+
+pub struct LinkWarning {
+    pending: Option<Link>, // masked link waiting for confirmation
+    always_trust: bool,
+    error: String,
+}
+
+impl LinkWarning {
+    pub fn new() -> Self {
+        Self{
+            pending: None,
+            always_trust: false,
+            error: "".into(),
+        }
+    }
+}
+
+impl DiscordApp {
+    // Masked links to untrusted domains and anything that isn't a web page are confirmed first
+    pub fn follow_link(&mut self, link: Link) {
+        if Url::parse(&link.url).is_err() {
+            println!("Not opening {}, it isn't a valid link", link.url);
+            return;
+        }
+        let masked = markdown::is_masked(&link) && !is_trusted(&self.config.trusted_link_domains, &link.url);
+        if masked || !is_web_url(&link.url) {
+            self.link_warning.pending = Some(link);
+            self.link_warning.always_trust = false;
+            self.link_warning.error.clear();
+            return;
+        }
+        if let Err(err) = open_url(&self.config.browser_name, &link.url) {
+            println!("Couldn't open {}: {}", link.url, err);
+        }
+    }
+
+    pub fn link_warning_window(&mut self, ctx: &Context) {
+        let Some(link) = self.link_warning.pending.clone() else {
+            return;
+        };
+        let web = is_web_url(&link.url);
+        let host = if web { link_host(&link.url).unwrap_or_default() } else { "".into() };
+        let mut open = true;
+        let mut close = false;
+        egui::Window::new("Leaving the app")
+            .id(egui::Id::new("link_warning_window"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let warning = &mut self.link_warning;
+                if web {
+                    ui.label("This link's text doesn't match where it goes.");
+                } else {
+                    ui.label("This link isn't a web page, it's opened by whatever program handles it.");
+                }
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("Shown as:");
+                    ui.strong(&link.text);
+                });
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("Goes to:");
+                    ui.label(RichText::new(&link.url).color(ui.visuals().warn_fg_color));
+                });
+                if !host.is_empty() {
+                    ui.checkbox(&mut warning.always_trust, format!("Always trust {}", host));
+                }
+                if !warning.error.is_empty() {
+                    ui.colored_label(ui.visuals().error_fg_color, &warning.error);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Open link").clicked() {
+                        match launch(&self.config.browser_name, &link.url) {
+                            Ok(()) => close = true,
+                            Err(err) => warning.error = format!("Couldn't open the link: {}", err),
+                        }
+                        // only remembered once the link actually opened
+                        let domains = &mut self.config.trusted_link_domains;
+                        if close && warning.always_trust && !host.is_empty()
+                            && !domains.iter().any(|domain| media_policy::host_matches(&host, domain)) {
+                            domains.push(host.clone());
+                            if let Err(err) = self.config.save_config(CONFIG_PATH) {
+                                // the dialog stays up so this is seen
+                                warning.error = format!("The link was opened, but {} couldn't be saved as trusted: {}", host, err);
+                                close = false;
+                            }
+                        }
+                    }
+                    if ui.button("Copy link").clicked() {
+                        ui.output_mut(|o| o.copied_text = link.url.clone());
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        if !open || close {
+            self.link_warning.pending = None;
+        }
+    }
+}
+
+// Returns true if anything was changed
+pub fn trusted_domains_editor(ui: &mut Ui, domains: &mut Vec<String>) -> bool {
+    if domains.is_empty() {
+        ui.weak("No trusted domains, masked links always ask");
+        return false;
+    }
+    let mut removed = None;
+    for (index, domain) in domains.iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(domain);
+            if ui.small_button("✖").clicked() {
+                removed = Some(index);
+            }
+        });
+    }
+    if let Some(index) = removed {
+        domains.remove(index);
+    }
+    removed.is_some()
+}
+
+fn link_host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(|host| host.to_lowercase())
+}
+
+fn is_web_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+fn is_trusted(domains: &[String], url: &str) -> bool {
+    let Some(host) = link_host(url) else {
+        return false;
+    };
+    domains.iter().any(|domain| media_policy::host_matches(&host, domain))
+}
+
+// Only web pages, other schemes are handed to arbitrary programs and go through the warning
+pub fn open_url(browser: &str, url: &str) -> io::Result<()> {
+    if !is_web_url(url) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "only http and https links are opened directly"));
+    }
+    launch(browser, url)
+}
+
+// In the configured browser, the system default if it's unset or can't be started
+fn launch(browser: &str, url: &str) -> io::Result<()> {
+    let browser = browser.trim();
+    if !browser.is_empty() {
+        match spawn(Command::new(browser).arg(url)) {
+            Ok(()) => return Ok(()),
+            Err(err) => println!("Couldn't start {}: {}, using the default browser", browser, err),
+        }
+    }
+    spawn(&mut default_browser(url))
+}
+
+#[cfg(target_os = "windows")]
+fn default_browser(url: &str) -> Command {
+    let mut command = Command::new("rundll32");
+    command.arg("url.dll,FileProtocolHandler").arg(url);
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn default_browser(url: &str) -> Command {
+    let mut command = Command::new("open");
+    command.arg(url);
+    command
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn default_browser(url: &str) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(url);
+    command
+}
+
+// The child is waited on elsewhere so it doesn't linger as a zombie
+fn spawn(command: &mut Command) -> io::Result<()> {
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
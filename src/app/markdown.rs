@@ -0,0 +1,789 @@
+use std::ops::Range;
+use egui::{Color32, CursorIcon, FontId, Galley, Id, Rect, Response, Sense, Stroke, Style, TextFormat, TextStyle, Ui, Vec2};
+use egui::text::{CCursor, LayoutJob};
+use egui_extras::syntax_highlighting::{self, CodeTheme};
+use crate::app::{animation, media};
+use crate::app::mentions::{self, Mention, MentionContext};
+use crate::discord::util;
+use crate::media_policy::RevealMode;
+
+// Discord flavored markdown, parsed into lines and code blocks then laid out as a single LayoutJob
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpanStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    pub spoiler: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: SpanStyle,
+    pub link: Option<String>,
+    pub mention: Option<Mention>, // the text is resolved when laid out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub text: String, // what's shown, the url itself unless masked
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Clicked {
+    Mention(Mention),
+    Link(Link),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineKind {
+    Paragraph,
+    Heading(u8),
+    Subtext,
+    Bullet(usize), // indentation depth
+    Numbered(usize, u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Line { kind: LineKind, quoted: bool, spans: Vec<Span> },
+    Code { language: String, code: String, quoted: bool },
+}
+
+pub fn parse(text: &str) -> Vec<Block> {
+    let mut blocks = vec![];
+    // ">>> " quotes everything until the end of the message
+    let mut quote_rest = false;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (lines, code_block) = split_code_block(rest);
+        for line in lines {
+            blocks.push(parse_line(line, &mut quote_rest));
+        }
+        match code_block {
+            Some((language, code, remaining)) => {
+                blocks.push(Block::Code { language, code, quoted: quote_rest });
+                rest = remaining;
+            }
+            None => break,
+        }
+    }
+    blocks
+}
+
+// Splits off the lines before the next closed code block, returning the block's language, code and the remaining text
+fn split_code_block(text: &str) -> (Vec<&str>, Option<(String, String, &str)>) {
+    let code_block = text.find("```").and_then(|start| {
+        let after = &text[start + 3..];
+        let end = after.find("```")?;
+        Some((start, &after[..end], &after[end + 3..]))
+    });
+    let Some((start, inner, remaining)) = code_block else {
+        return (text.lines().collect(), None);
+    };
+    let mut before = &text[..start];
+    // the fence usually sits on its own line
+    if let Some(stripped) = before.strip_suffix('\n') {
+        before = stripped;
+    }
+    let lines = if before.is_empty() { vec![] } else { before.lines().collect() };
+    let (language, code) = match inner.split_once('\n') {
+        Some((first, code)) if !first.trim().is_empty() && !first.trim().contains(' ') => (first.trim().to_string(), code),
+        Some((first, code)) if first.trim().is_empty() => (String::new(), code),
+        _ => (String::new(), inner),
+    };
+    let code = code.strip_suffix('\n').unwrap_or(code);
+    let remaining = remaining.strip_prefix('\n').unwrap_or(remaining);
+    (lines, Some((language, code.to_string(), remaining)))
+}
+
+fn parse_line(mut line: &str, quote_rest: &mut bool) -> Block {
+    let mut quoted = *quote_rest;
+    if !quoted {
+        if let Some(stripped) = line.strip_prefix(">>> ") {
+            *quote_rest = true;
+            quoted = true;
+            line = stripped;
+        } else if let Some(stripped) = line.strip_prefix("> ") {
+            quoted = true;
+            line = stripped;
+        }
+    }
+    let (kind, content) = line_kind(line);
+    let chars: Vec<char> = content.chars().collect();
+    let mut spans = vec![];
+    parse_inline(&chars, SpanStyle::default(), None, &mut spans);
+    Block::Line { kind, quoted, spans }
+}
+
+fn line_kind(line: &str) -> (LineKind, &str) {
+    for (prefix, level) in [("# ", 1), ("## ", 2), ("### ", 3)] {
+        if let Some(content) = line.strip_prefix(prefix) {
+            return (LineKind::Heading(level), content);
+        }
+    }
+    if let Some(content) = line.strip_prefix("-# ") {
+        return (LineKind::Subtext, content);
+    }
+    let trimmed = line.trim_start_matches(' ');
+    let depth = (line.len() - trimmed.len()) / 2;
+    if let Some(content) = trimmed.strip_prefix("- ").or(trimmed.strip_prefix("* ")) {
+        return (LineKind::Bullet(depth), content);
+    }
+    if let Some((number, content)) = trimmed.split_once(". ") {
+        if !number.is_empty() && number.len() <= 9 && number.chars().all(|c| c.is_ascii_digit()) {
+            return (LineKind::Numbered(depth, number.parse().unwrap()), content);
+        }
+    }
+    (LineKind::Paragraph, line)
+}
+
+const ESCAPABLE: &str = "\\*_~`|>#-[]()";
+const DELIMITERS: [&str; 6] = ["||", "**", "__", "~~", "*", "_"];
+
+fn parse_inline(chars: &[char], style: SpanStyle, link: Option<&str>, spans: &mut Vec<Span>) {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && i + 1 < chars.len() && ESCAPABLE.contains(chars[i + 1]) {
+            text.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        if let Some((mention, end)) = mentions::parse_mention(chars, i) {
+            push_span(spans, &mut text, style, link);
+            spans.push(Span { text: String::new(), style, link: None, mention: Some(mention) });
+            i = end;
+            continue;
+        }
+        if link.is_none() {
+            if let Some((label, url, end)) = masked_link(chars, i) {
+                push_span(spans, &mut text, style, None);
+                parse_inline(label, style, Some(&url), spans);
+                i = end;
+                continue;
+            }
+            if let Some(url) = bare_link(chars, i) {
+                push_span(spans, &mut text, style, None);
+                let len = url.chars().count();
+                spans.push(Span { text: url.clone(), style, link: Some(url), mention: None });
+                i += len;
+                continue;
+            }
+        }
+        if c == '`' {
+            let run = run_length(chars, i, '`');
+            let fence: Vec<char> = vec!['`'; run];
+            let closing = (i + run..chars.len()).find(|&j| chars[j..].starts_with(&fence) && run_length(chars, j, '`') == run);
+            if let Some(end) = closing.filter(|&end| end > i + run) {
+                push_span(spans, &mut text, style, link);
+                let code: String = chars[i + run..end].iter().collect();
+                let style = SpanStyle { code: true, ..style };
+                spans.push(Span { text: code, style, link: link.map(String::from), mention: None });
+                i = end + run;
+                continue;
+            }
+            text.extend(&chars[i..i + run]);
+            i += run;
+            continue;
+        }
+        let delimiter = DELIMITERS.iter().find(|delimiter| starts_with(chars, i, delimiter));
+        if let Some(delimiter) = delimiter {
+            let len = delimiter.len();
+            if let Some(end) = find_closing(chars, i, delimiter) {
+                push_span(spans, &mut text, style, link);
+                let mut inner = style;
+                match *delimiter {
+                    "**" => inner.bold = true,
+                    "__" => inner.underline = true,
+                    "~~" => inner.strikethrough = true,
+                    "||" => inner.spoiler = true,
+                    _ => inner.italic = true,
+                }
+                parse_inline(&chars[i + len..end], inner, link, spans);
+                i = end + len;
+                continue;
+            }
+        }
+        text.push(c);
+        i += 1;
+    }
+    push_span(spans, &mut text, style, link);
+}
+
+fn push_span(spans: &mut Vec<Span>, text: &mut String, style: SpanStyle, link: Option<&str>) {
+    if text.is_empty() {
+        return;
+    }
+    let link = link.map(String::from);
+    // merge with the previous span when nothing changed, escapes and unmatched delimiters split text otherwise
+    if let Some(last) = spans.last_mut() {
+        if last.style == style && last.link == link && last.mention.is_none() {
+            last.text.push_str(text);
+            text.clear();
+            return;
+        }
+    }
+    spans.push(Span { text: std::mem::take(text), style, link, mention: None });
+}
+
+fn starts_with(chars: &[char], i: usize, pattern: &str) -> bool {
+    pattern.chars()
+        .enumerate()
+        .all(|(offset, c)| chars.get(i + offset) == Some(&c))
+}
+
+fn run_length(chars: &[char], i: usize, c: char) -> usize {
+    chars[i..].iter().take_while(|&&other| other == c).count()
+}
+
+fn is_word_char(c: Option<&char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric())
+}
+
+// The index of the delimiter closing the one at `start`, content must not be empty
+fn find_closing(chars: &[char], start: usize, delimiter: &str) -> Option<usize> {
+    let len = delimiter.len();
+    let marker = delimiter.chars().next().unwrap();
+    // snake_case words aren't italicized
+    if delimiter == "_" && start > 0 && is_word_char(chars.get(start - 1)) {
+        return None;
+    }
+    // "2 * 3 * 4" isn't italic either
+    if delimiter == "*" && chars.get(start + 1).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    let mut j = start + len + 1;
+    while j < chars.len() {
+        let c = chars[j];
+        if c == '\\' {
+            j += 2;
+            continue;
+        }
+        if c == '`' {
+            let run = run_length(chars, j, '`');
+            let fence: Vec<char> = vec!['`'; run];
+            match (j + run..chars.len()).find(|&k| chars[k..].starts_with(&fence)) {
+                Some(end) => j = end + run,
+                None => j += run,
+            }
+            continue;
+        }
+        if c != marker {
+            j += 1;
+            continue;
+        }
+        let run = run_length(chars, j, marker);
+        if len == 1 {
+            // a double run inside single delimiters belongs to a nested bold or underline
+            if run == 2 {
+                j += 2;
+                continue;
+            }
+            if delimiter == "_" && is_word_char(chars.get(j + 1)) {
+                j += run;
+                continue;
+            }
+            return Some(j);
+        }
+        if run >= len {
+            // "***text***" closes bold on the last two of the run, leaving italics inside
+            return Some(j + run - len);
+        }
+        j += run;
+    }
+    None
+}
+
+// [label](https://url)
+fn masked_link(chars: &[char], start: usize) -> Option<(&[char], String, usize)> {
+    if chars[start] != '[' {
+        return None;
+    }
+    let label_end = (start + 1..chars.len()).find(|&j| chars[j] == ']')?;
+    if chars.get(label_end + 1) != Some(&'(') || label_end == start + 1 {
+        return None;
+    }
+    let url_start = label_end + 2;
+    let url_end = (url_start..chars.len()).find(|&j| chars[j] == ')')?;
+    let url: String = chars[url_start..url_end].iter().collect();
+    let url = url.trim_start_matches('<').trim_end_matches('>').to_string();
+    if !is_url(&url) || url.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((&chars[start + 1..label_end], url, url_end + 1))
+}
+
+fn bare_link(chars: &[char], start: usize) -> Option<String> {
+    if start > 0 && !chars[start - 1].is_whitespace() && chars[start - 1] != '<' {
+        return None;
+    }
+    if !starts_with(chars, start, "https://") && !starts_with(chars, start, "http://") {
+        return None;
+    }
+    let end = (start..chars.len()).find(|&j| chars[j].is_whitespace() || chars[j] == '>').unwrap_or(chars.len());
+    let url: String = chars[start..end].iter().collect();
+    let url = url.trim_end_matches(['.', ',', ':', ';', '!', '?', ')', '*', '_', '~', '|']);
+    is_url(url).then(|| url.to_string())
+}
+
+fn is_url(url: &str) -> bool {
+    let host = url.strip_prefix("https://").or(url.strip_prefix("http://"));
+    host.is_some_and(|host| !host.is_empty())
+}
+
+// A masked link reads as something other than where it goes
+pub fn is_masked(link: &Link) -> bool {
+    fn normalize(url: &str) -> String {
+        let url = url.trim();
+        let url = url.strip_prefix("https://").or(url.strip_prefix("http://")).unwrap_or(url);
+        url.trim_end_matches('/').to_lowercase()
+    }
+    normalize(&link.text) != normalize(&link.url)
+}
+
+pub struct MessageLayout {
+    pub job: LayoutJob,
+    pub mentions: Vec<(Range<usize>, Mention)>, // byte ranges in the job's text
+    pub emojis: Vec<(usize, String)>, // char index of the placeholder and the emoji url
+    pub links: Vec<(Range<usize>, Link)>, // byte ranges in the job's text
+    pub spoilers: Vec<Range<usize>>, // char ranges, covered until clicked
+}
+
+pub fn layout_job(ctx: &egui::Context, style: &Style, text: &str, context: &MentionContext) -> MessageLayout {
+    let mut job = LayoutJob::default();
+    let mut mentions = vec![];
+    let mut emojis = vec![];
+    let mut links: Vec<(Range<usize>, Link)> = vec![];
+    let mut spoilers: Vec<Range<usize>> = vec![];
+    let body = TextStyle::Body.resolve(style);
+    let plain = TextFormat::simple(body.clone(), style.visuals.text_color());
+    let quote_bar = TextFormat::simple(body.clone(), style.visuals.weak_text_color());
+    let theme = CodeTheme::from_style(style);
+    // emojis are painted over a transparent space stretched to the row height
+    let (space_width, emoji_size) = ctx.fonts(|fonts| (fonts.glyph_width(&body, ' '), fonts.row_height(&body)));
+    let mut emoji_placeholder = TextFormat::simple(body.clone(), Color32::TRANSPARENT);
+    emoji_placeholder.extra_letter_spacing = emoji_size - space_width;
+    for (index, block) in parse(text).iter().enumerate() {
+        if index > 0 {
+            job.append("\n", 0.0, plain.clone());
+        }
+        match block {
+            Block::Line { kind, quoted, spans } => {
+                if *quoted {
+                    job.append("▎ ", 0.0, quote_bar.clone());
+                }
+                match kind {
+                    LineKind::Bullet(depth) => job.append(&format!("{}• ", "    ".repeat(*depth)), 0.0, plain.clone()),
+                    LineKind::Numbered(depth, number) => job.append(&format!("{}{}. ", "    ".repeat(*depth), number), 0.0, plain.clone()),
+                    _ => {}
+                }
+                for span in spans {
+                    let mut format = span_format(style, &body, *kind, span);
+                    let spoiler_start = if span.style.spoiler { job.text.chars().count() } else { 0 };
+                    let Some(mention) = &span.mention else {
+                        let start = job.text.len();
+                        if span.style.spoiler {
+                            add_spoiler(&mut spoilers, spoiler_start, spoiler_start + span.text.chars().count());
+                        }
+                        job.append(&span.text, 0.0, format);
+                        if let Some(url) = &span.link {
+                            // a masked label split by formatting is still one link
+                            match links.last_mut() {
+                                Some((range, link)) if range.end == start && link.url == *url => {
+                                    range.end = job.text.len();
+                                    link.text.push_str(&span.text);
+                                }
+                                _ => links.push((start..job.text.len(), Link { text: span.text.clone(), url: url.clone() })),
+                            }
+                        }
+                        continue;
+                    };
+                    if let Mention::Emoji(id, animated) = mention {
+                        emojis.push((job.text.chars().count(), util::emoji_url(*id, *animated)));
+                        job.append(" ", 0.0, emoji_placeholder.clone());
+                        if span.style.spoiler {
+                            add_spoiler(&mut spoilers, spoiler_start, spoiler_start + 1);
+                        }
+                        continue;
+                    }
+                    let (color, background) = context.colors(mention, &style.visuals);
+                    format.color = color;
+                    format.background = background;
+                    let start = job.text.len();
+                    // padding so the background reads as a pill
+                    job.append(&format!(" {} ", context.label(mention)), 0.0, format);
+                    mentions.push((start..job.text.len(), *mention));
+                    if span.style.spoiler {
+                        add_spoiler(&mut spoilers, spoiler_start, job.text.chars().count());
+                    }
+                }
+            }
+            Block::Code { language, code, quoted } => {
+                if *quoted {
+                    job.append("▎ ", 0.0, quote_bar.clone());
+                }
+                let language = if language.is_empty() { "txt" } else { language.as_str() };
+                let highlighted = syntax_highlighting::highlight(ctx, &theme, code, language);
+                let offset = job.text.len();
+                job.text.push_str(&highlighted.text);
+                for mut section in highlighted.sections {
+                    section.byte_range = section.byte_range.start + offset..section.byte_range.end + offset;
+                    section.format.background = style.visuals.code_bg_color;
+                    job.sections.push(section);
+                }
+            }
+        }
+    }
+    MessageLayout { job, mentions, emojis, links, spoilers }
+}
+
+// Adjacent spoiler spans are revealed together
+fn add_spoiler(spoilers: &mut Vec<Range<usize>>, start: usize, end: usize) {
+    match spoilers.last_mut() {
+        Some(last) if last.end == start => last.end = end,
+        _ => spoilers.push(start..end),
+    }
+}
+
+// The rectangles a char range covers, one per row
+fn range_rects(galley: &Galley, range: &Range<usize>) -> Vec<Rect> {
+    let start = galley.from_ccursor(CCursor::new(range.start));
+    let end = galley.from_ccursor(CCursor::new(range.end));
+    (start.rcursor.row..=end.rcursor.row)
+        .filter_map(|row| {
+            let row_rect = galley.rows.get(row)?.rect;
+            let left = if row == start.rcursor.row { galley.pos_from_cursor(&start).min.x } else { row_rect.min.x };
+            let right = if row == end.rcursor.row { galley.pos_from_cursor(&end).min.x } else { row_rect.max.x };
+            (right > left).then(|| Rect::from_x_y_ranges(left..=right, row_rect.min.y..=row_rect.max.y))
+        })
+        .collect()
+}
+
+// Shows the laid out message, returning the mention or link under the pointer if it was clicked
+pub fn show(ui: &mut Ui, mut layout: MessageLayout, reveal: RevealMode) -> (Response, Option<Clicked>) {
+    layout.job.wrap.max_width = ui.available_width();
+    // spoilers are told apart by the text, identical messages reveal together
+    let text_id = Id::new(("spoiler", &layout.job.text));
+    let galley = ui.fonts(|fonts| fonts.layout_job(layout.job));
+    let (rect, mut response) = ui.allocate_exact_size(galley.size(), Sense::click());
+    let mut covered = vec![];
+    let mut revealed = vec![];
+    for (index, range) in layout.spoilers.iter().enumerate() {
+        let id = text_id.with(index);
+        let rects: Vec<Rect> = range_rects(&galley, range).iter().map(|row| row.translate(rect.min.to_vec2())).collect();
+        if media::is_covered(ui, id, reveal) {
+            covered.push((id, rects));
+        } else {
+            revealed.extend(rects);
+        }
+    }
+    let pointer = response.hover_pos();
+    let hovered_spoiler = pointer.and_then(|pos| {
+        covered.iter().find(|(_, rects)| rects.iter().any(|row| row.contains(pos)))
+    });
+    if let Some((id, _)) = hovered_spoiler {
+        if reveal == RevealMode::Click {
+            ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+            if response.clicked() {
+                media::reveal(ui, *id);
+            }
+        }
+    }
+    let hovered = pointer.filter(|_| hovered_spoiler.is_none()).and_then(|pos| {
+        let cursor = galley.cursor_from_pos(pos - rect.min);
+        let index = galley.text().char_indices().nth(cursor.ccursor.index)?.0;
+        let mention = layout.mentions.iter()
+            .find(|(range, _)| range.contains(&index))
+            .map(|(_, mention)| Clicked::Mention(*mention));
+        mention.or_else(|| layout.links.iter()
+            .find(|(range, _)| range.contains(&index))
+            .map(|(_, link)| Clicked::Link(link.clone())))
+    });
+    if hovered.is_some() {
+        ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+    }
+    if let Some(Clicked::Link(link)) = &hovered {
+        response = response.on_hover_text_at_pointer(&link.url);
+    }
+    for row in revealed {
+        ui.painter().rect_filled(row, 2.0, ui.visuals().faint_bg_color);
+    }
+    for (index, url) in &layout.emojis {
+        let cursor = galley.from_ccursor(CCursor::new(*index));
+        let row = galley.pos_from_cursor(&cursor).translate(rect.min.to_vec2());
+        let emoji = Rect::from_min_size(row.min, Vec2::splat(row.height()));
+        if ui.is_rect_visible(emoji) {
+            animation::paint_at(ui, url, emoji);
+        }
+    }
+    ui.painter().galley(rect.min, galley);
+    for row in covered.iter().flat_map(|(_, rects)| rects) {
+        media::paint_cover(ui, *row, 2.0);
+    }
+    let clicked = if response.clicked() { hovered } else { None };
+    (response, clicked)
+}
+
+fn span_format(style: &Style, body: &FontId, kind: LineKind, span: &Span) -> TextFormat {
+    let visuals = &style.visuals;
+    let mut font = body.clone();
+    let mut color = visuals.text_color();
+    match kind {
+        LineKind::Heading(level) => {
+            font.size *= match level {
+                1 => 1.5,
+                2 => 1.3,
+                _ => 1.15,
+            };
+            color = visuals.strong_text_color();
+        }
+        LineKind::Subtext => {
+            font.size *= 0.8;
+            color = visuals.weak_text_color();
+        }
+        _ => {}
+    }
+    // the default fonts have no bold face
+    if span.style.bold {
+        color = visuals.strong_text_color();
+    }
+    let mut format = TextFormat::simple(font.clone(), color);
+    format.italics = span.style.italic;
+    if span.style.code {
+        format.font_id = FontId::monospace(font.size * 0.9);
+        format.background = visuals.code_bg_color;
+    }
+    if span.link.is_some() {
+        format.color = visuals.hyperlink_color;
+        format.underline = Stroke::new(1.0, visuals.hyperlink_color);
+    }
+    if span.style.underline {
+        format.underline = Stroke::new(1.0, format.color);
+    }
+    if span.style.strikethrough {
+        format.strikethrough = Stroke::new(1.0, format.color);
+    }
+    format
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Span {
+        Span { text: text.into(), style: SpanStyle::default(), link: None, mention: None }
+    }
+
+    fn styled(text: &str, style: SpanStyle) -> Span {
+        Span { text: text.into(), style, link: None, mention: None }
+    }
+
+    fn link(text: &str, url: &str) -> Span {
+        Span { text: text.into(), style: SpanStyle::default(), link: Some(url.into()), mention: None }
+    }
+
+    fn mention(mention: Mention) -> Span {
+        Span { text: "".into(), style: SpanStyle::default(), link: None, mention: Some(mention) }
+    }
+
+    fn line_spans(text: &str) -> Vec<Span> {
+        match parse(text).remove(0) {
+            Block::Line { spans, .. } => spans,
+            block => panic!("expected a line, got {:?}", block),
+        }
+    }
+
+    const BOLD: SpanStyle = SpanStyle { bold: true, italic: false, underline: false, strikethrough: false, code: false, spoiler: false };
+    const ITALIC: SpanStyle = SpanStyle { bold: false, italic: true, underline: false, strikethrough: false, code: false, spoiler: false };
+    const CODE: SpanStyle = SpanStyle { bold: false, italic: false, underline: false, strikethrough: false, code: true, spoiler: false };
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(line_spans("hello there"), vec![plain("hello there")]);
+    }
+
+    #[test]
+    fn bold_and_italic() {
+        assert_eq!(line_spans("a **bold** and *italic* word"), vec![
+            plain("a "),
+            styled("bold", BOLD),
+            plain(" and "),
+            styled("italic", ITALIC),
+            plain(" word"),
+        ]);
+    }
+
+    #[test]
+    fn bold_italic_triple_stars() {
+        let bold_italic = SpanStyle { bold: true, italic: true, ..SpanStyle::default() };
+        assert_eq!(line_spans("***both***"), vec![styled("both", bold_italic)]);
+    }
+
+    #[test]
+    fn underline_and_strikethrough() {
+        let underline = SpanStyle { underline: true, ..SpanStyle::default() };
+        let strikethrough = SpanStyle { strikethrough: true, ..SpanStyle::default() };
+        assert_eq!(line_spans("__under__ ~~gone~~"), vec![
+            styled("under", underline),
+            plain(" "),
+            styled("gone", strikethrough),
+        ]);
+    }
+
+    #[test]
+    fn snake_case_is_not_italic() {
+        assert_eq!(line_spans("call some_long_name now"), vec![plain("call some_long_name now")]);
+        assert_eq!(line_spans("_italic_"), vec![styled("italic", ITALIC)]);
+    }
+
+    #[test]
+    fn unmatched_delimiters_stay_literal() {
+        assert_eq!(line_spans("2 * 3 = 6 and **open"), vec![plain("2 * 3 = 6 and **open")]);
+    }
+
+    #[test]
+    fn escaped_delimiters() {
+        assert_eq!(line_spans(r"\*not italic\*"), vec![plain("*not italic*")]);
+    }
+
+    #[test]
+    fn inline_code_is_not_parsed() {
+        assert_eq!(line_spans("run `cargo **build**` now"), vec![
+            plain("run "),
+            styled("cargo **build**", CODE),
+            plain(" now"),
+        ]);
+        assert_eq!(line_spans("``a ` b``"), vec![styled("a ` b", CODE)]);
+    }
+
+    #[test]
+    fn masked_and_bare_links() {
+        assert_eq!(line_spans("see [the docs](https://docs.rs) or https://crates.io."), vec![
+            plain("see "),
+            link("the docs", "https://docs.rs"),
+            plain(" or "),
+            link("https://crates.io", "https://crates.io"),
+            plain("."),
+        ]);
+        assert_eq!(line_spans("[not a link](ftp://x)"), vec![plain("[not a link](ftp://x)")]);
+    }
+
+    #[test]
+    fn spoilers() {
+        let spoiler = SpanStyle { spoiler: true, ..SpanStyle::default() };
+        let bold_spoiler = SpanStyle { bold: true, spoiler: true, ..SpanStyle::default() };
+        assert_eq!(line_spans("it was ||the **butler**|| all along"), vec![
+            plain("it was "),
+            styled("the ", spoiler),
+            styled("butler", bold_spoiler),
+            plain(" all along"),
+        ]);
+        assert_eq!(line_spans("||a|| and ||b||"), vec![styled("a", spoiler), plain(" and "), styled("b", spoiler)]);
+        assert_eq!(line_spans("||||"), vec![plain("||||")]);
+    }
+
+    #[test]
+    fn masked_links() {
+        let link = |text: &str, url: &str| Link { text: text.into(), url: url.into() };
+        assert!(!is_masked(&link("https://crates.io", "https://crates.io")));
+        assert!(!is_masked(&link("crates.io/", "https://Crates.io")));
+        assert!(is_masked(&link("the docs", "https://docs.rs")));
+        assert!(is_masked(&link("https://docs.rs", "https://evil.net")));
+    }
+
+    #[test]
+    fn headings_lists_and_subtext() {
+        let kinds: Vec<LineKind> = parse("# Title\n### Small\n- one\n  - nested\n2. two\n-# fine print\nplain")
+            .into_iter()
+            .map(|block| match block {
+                Block::Line { kind, .. } => kind,
+                Block::Code { .. } => panic!("unexpected code block"),
+            })
+            .collect();
+        assert_eq!(kinds, vec![
+            LineKind::Heading(1),
+            LineKind::Heading(3),
+            LineKind::Bullet(0),
+            LineKind::Bullet(1),
+            LineKind::Numbered(0, 2),
+            LineKind::Subtext,
+            LineKind::Paragraph,
+        ]);
+        assert_eq!(line_spans("#hashtag"), vec![plain("#hashtag")]);
+    }
+
+    #[test]
+    fn block_quotes() {
+        let quoted: Vec<bool> = parse("> quoted\nnot quoted\n>>> rest\nof the\nmessage")
+            .into_iter()
+            .map(|block| match block {
+                Block::Line { quoted, .. } => quoted,
+                Block::Code { quoted, .. } => quoted,
+            })
+            .collect();
+        assert_eq!(quoted, vec![true, false, true, true, true]);
+    }
+
+    #[test]
+    fn code_blocks() {
+        let blocks = parse("look:\n```rs\nfn main() {\n    println!(\"**hi**\");\n}\n```\ndone");
+        assert_eq!(blocks, vec![
+            Block::Line { kind: LineKind::Paragraph, quoted: false, spans: vec![plain("look:")] },
+            Block::Code {
+                language: "rs".into(),
+                code: "fn main() {\n    println!(\"**hi**\");\n}".into(),
+                quoted: false,
+            },
+            Block::Line { kind: LineKind::Paragraph, quoted: false, spans: vec![plain("done")] },
+        ]);
+    }
+
+    #[test]
+    fn single_line_and_unclosed_code_blocks() {
+        assert_eq!(parse("```one liner```"), vec![
+            Block::Code { language: "".into(), code: "one liner".into(), quoted: false },
+        ]);
+        assert_eq!(line_spans("```never closed"), vec![plain("```never closed")]);
+    }
+
+    #[test]
+    fn mentions() {
+        assert_eq!(line_spans("hi <@123> and <@!45>, see <#678> <@&9>"), vec![
+            plain("hi "),
+            mention(Mention::User(123)),
+            plain(" and "),
+            mention(Mention::User(45)),
+            plain(", see "),
+            mention(Mention::Channel(678)),
+            plain(" "),
+            mention(Mention::Role(9)),
+        ]);
+        assert_eq!(line_spans("a <b> c <@abc>"), vec![plain("a <b> c <@abc>")]);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(line_spans("<t:1700000000:R> <t:1700000000>"), vec![
+            mention(Mention::Timestamp(1700000000, 'R')),
+            plain(" "),
+            mention(Mention::Timestamp(1700000000, 'f')),
+        ]);
+        assert_eq!(line_spans("<t:1700000000:x>"), vec![plain("<t:1700000000:x>")]);
+    }
+
+    #[test]
+    fn custom_emojis() {
+        assert_eq!(line_spans("gg <:pog_champ:123><a:dance:456>"), vec![
+            plain("gg "),
+            mention(Mention::Emoji(123, false)),
+            mention(Mention::Emoji(456, true)),
+        ]);
+        assert_eq!(line_spans("<:x:1> <:no spaces:2>"), vec![plain("<:x:1> <:no spaces:2>")]);
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+use egui::{Align2, Color32, CursorIcon, FontId, Id, Rect, Response, RichText, Ui};
+use crate::discord::shared_cache::Snapshot;
+use crate::discord::util;
+use crate::media_policy::{DomainAction, MediaDecision, MediaPolicy, RevealMode};
+
+const ACTIONS: [(&str, DomainAction); 3] = [
+    ("Autoload", DomainAction::Autoload),
+    ("Click to load", DomainAction::ClickToLoad),
+    ("Block", DomainAction::Block),
+];
+
+// The media policy as applied while rendering, along with what the user chose to load anyway
+#[derive(Clone, Copy)]
+pub struct MediaGate<'a> {
+    policy: &'a MediaPolicy,
+    loaded: &'a Snapshot<HashSet<String>>, // urls loaded once by hand
+    load_all: bool, // the message's images were requested through "Load image"
+    reveal: RevealMode,
+    nsfw: bool, // every image is treated as a spoiler
+}
+
+impl<'a> MediaGate<'a> {
+    pub fn new(policy: &'a MediaPolicy, loaded: &'a Snapshot<HashSet<String>>) -> Self {
+        Self{ policy, loaded, load_all: false, reveal: RevealMode::Always, nsfw: false }
+    }
+
+    pub fn load_all(&self, load_all: bool) -> Self {
+        Self{ load_all, ..*self }
+    }
+
+    pub fn spoilers(&self, reveal: RevealMode, nsfw: bool) -> Self {
+        Self{ reveal, nsfw, ..*self }
+    }
+
+    pub fn reveal_mode(&self) -> RevealMode {
+        self.reveal
+    }
+
+    // Spoilers stay covered until clicked, as does everything in an NSFW channel
+    pub fn covered(&self, ui: &Ui, id: Id, spoiler: bool) -> bool {
+        (spoiler || self.nsfw) && is_covered(ui, id, self.reveal)
+    }
+
+    // Painted in place of covered media, a click reveals it unless spoilers are never shown
+    pub fn cover(&self, ui: &Ui, rect: Rect, response: &Response, id: Id) {
+        let label = match self.reveal {
+            RevealMode::Never => "HIDDEN",
+            _ => if self.nsfw { "NSFW" } else { "SPOILER" },
+        };
+        paint_cover(ui, rect, 5.0);
+        ui.painter().text(rect.center(), Align2::CENTER_CENTER, label, FontId::proportional(14.0), Color32::WHITE);
+        if self.reveal == RevealMode::Click {
+            if response.hovered() {
+                ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+            }
+            if response.clicked() {
+                reveal(ui, id);
+            }
+        }
+    }
+
+    pub fn allows(&self, url: &str, mime: Option<&str>) -> bool {
+        match self.policy.decide(url, mime) {
+            MediaDecision::Load => true,
+            MediaDecision::ClickToLoad => self.load_all || self.loaded.load().contains(url),
+            MediaDecision::Blocked(_) => self.loaded.load().contains(url),
+        }
+    }
+
+    // Stands in for media that isn't allowed, with a button to load it
+    pub fn placeholder(&self, ui: &mut Ui, url: &str, mime: Option<&str>, label: &str) {
+        let shown_url = util::strip_parameters(url.to_string());
+        ui.horizontal_wrapped(|ui| {
+            ui.weak(format!("[{}]", label)).on_hover_text(&shown_url);
+            let button = match self.policy.decide(url, mime) {
+                MediaDecision::Blocked(reason) => {
+                    ui.label(RichText::new(format!("blocked, {}", reason)).small().color(ui.visuals().warn_fg_color));
+                    "Load anyway once"
+                }
+                _ => "Load",
+            };
+            if ui.small_button(button).on_hover_text(&shown_url).clicked() {
+                self.loaded.update(|loaded| loaded.insert(url.to_string()));
+            }
+        });
+    }
+}
+
+pub fn is_covered(ui: &Ui, id: Id, reveal: RevealMode) -> bool {
+    match reveal {
+        RevealMode::Always => false,
+        RevealMode::Never => true,
+        RevealMode::Click => !ui.data(|data| data.get_temp::<bool>(id)).unwrap_or(false),
+    }
+}
+
+// Revealed for the rest of the session
+pub fn reveal(ui: &Ui, id: Id) {
+    ui.data_mut(|data| data.insert_temp(id, true));
+}
+
+pub fn paint_cover(ui: &Ui, rect: Rect, rounding: f32) {
+    ui.painter().rect_filled(rect, rounding, Color32::from_gray(32));
+}
+
+pub fn reveal_mode_combo(ui: &mut Ui, reveal: &mut RevealMode) -> bool {
+    let modes = [
+        ("Click to reveal", RevealMode::Click),
+        ("Always show", RevealMode::Always),
+        ("Never show", RevealMode::Never),
+    ];
+    let selected = modes.iter().find(|(_, mode)| mode == reveal).map_or("", |(name, _)| name);
+    let mut changed = false;
+    egui::ComboBox::from_id_source("reveal_mode")
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            for (name, mode) in modes {
+                changed |= ui.selectable_value(reveal, mode, name).changed();
+            }
+        });
+    changed
+}
+
+// Returns true if anything was changed
+pub fn policy_editor(ui: &mut Ui, policy: &mut MediaPolicy, new_domain: &mut String) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Other domains");
+        changed |= action_combo(ui, "unlisted_action", &mut policy.unlisted);
+    });
+    let mut removed = None;
+    egui::Grid::new("domain_rules").num_columns(3).striped(true).show(ui, |ui| {
+        for (index, rule) in policy.domains.iter_mut().enumerate() {
+            ui.label(&rule.domain);
+            changed |= action_combo(ui, ("domain_action", index), &mut rule.action);
+            if ui.small_button("✖").clicked() {
+                removed = Some(index);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(index) = removed {
+        policy.domains.remove(index);
+        changed = true;
+    }
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(new_domain).hint_text("example.com").desired_width(150.0));
+        if ui.button("Add domain").clicked() && !new_domain.trim().is_empty() {
+            policy.set_domain_action(new_domain, DomainAction::Autoload);
+            new_domain.clear();
+            changed = true;
+        }
+    });
+    ui.label("Extensions");
+    changed |= list_edit(ui, "extensions", &mut policy.extensions);
+    ui.label("MIME types");
+    changed |= list_edit(ui, "mime_types", &mut policy.mime_types);
+    changed
+}
+
+fn action_combo(ui: &mut Ui, id: impl std::hash::Hash, action: &mut DomainAction) -> bool {
+    let selected = ACTIONS.iter().find(|(_, value)| value == action).map_or("", |(name, _)| name);
+    let mut changed = false;
+    egui::ComboBox::from_id_source(id)
+        .selected_text(selected)
+        .show_ui(ui, |ui| {
+            for (name, value) in ACTIONS {
+                changed |= ui.selectable_value(action, value, name).changed();
+            }
+        });
+    changed
+}
+
+// Comma separated, edited as one line
+fn list_edit(ui: &mut Ui, id: &str, list: &mut Vec<String>) -> bool {
+    let id = ui.make_persistent_id(id);
+    let mut text = ui.data_mut(|data| data.get_temp::<String>(id)).unwrap_or(list.join(", "));
+    let response = ui.text_edit_singleline(&mut text);
+    let changed = response.lost_focus();
+    if changed {
+        *list = text.split(',').map(|item| item.trim().to_lowercase()).filter(|item| !item.is_empty()).collect();
+        ui.data_mut(|data| data.remove::<String>(id));
+    } else if response.has_focus() {
+        ui.data_mut(|data| data.insert_temp(id, text));
+    }
+    changed
+}
@@ -0,0 +1,112 @@
+use egui::{Color32, Visuals};
+use twilight_model::channel::Channel;
+use twilight_model::channel::message;
+use twilight_model::guild::Member;
+use crate::discord::guild::Server;
+use crate::discord::util;
+use crate::time_format::TimeFormat;
+
+// <@id>, <@!id>, <#id>, <@&id>, <t:secs:style> and <:name:id> tokens in message content
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mention {
+    User(u64),
+    Channel(u64),
+    Role(u64),
+    Timestamp(i64, char),
+    Emoji(u64, bool), // animated
+}
+
+// Returns the mention starting at `start` and the index right after it
+pub fn parse_mention(chars: &[char], start: usize) -> Option<(Mention, usize)> {
+    if chars[start] != '<' {
+        return None;
+    }
+    // emoji names are at most 32 characters
+    let end = (start + 1..chars.len()).take(64).find(|&j| chars[j] == '>')?;
+    let inner: String = chars[start + 1..end].iter().collect();
+    let mention = if let Some(timestamp) = inner.strip_prefix("t:") {
+        let (secs, style) = match timestamp.split_once(':') {
+            Some((secs, style)) => (secs, style),
+            None => (timestamp, "f"),
+        };
+        let style = match style {
+            "t" | "T" | "d" | "D" | "f" | "F" | "R" => style.chars().next().unwrap(),
+            _ => return None,
+        };
+        Mention::Timestamp(secs.parse().ok()?, style)
+    } else if let Some(id) = inner.strip_prefix("@&") {
+        Mention::Role(parse_id(id)?)
+    } else if let Some(id) = inner.strip_prefix("@!").or(inner.strip_prefix('@')) {
+        Mention::User(parse_id(id)?)
+    } else if let Some(id) = inner.strip_prefix('#') {
+        Mention::Channel(parse_id(id)?)
+    } else if inner.starts_with(':') || inner.starts_with("a:") {
+        let (name, id) = inner.trim_start_matches('a').trim_start_matches(':').split_once(':')?;
+        if name.len() < 2 || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        Mention::Emoji(parse_id(id)?, inner.starts_with('a'))
+    } else {
+        return None;
+    };
+    Some((mention, end + 1))
+}
+
+fn parse_id(id: &str) -> Option<u64> {
+    id.parse().ok().filter(|id| *id != 0)
+}
+
+// What mentions are resolved against when laying out a message
+pub struct MentionContext<'a> {
+    pub server: Option<&'a Server>,
+    pub members: &'a [Member],
+    pub channels: &'a (Vec<Channel>, Vec<Channel>),
+    pub users: &'a [message::Mention], // users mentioned by the message itself
+    pub time: &'a TimeFormat,
+}
+
+impl<'a> MentionContext<'a> {
+    pub fn label(&self, mention: &Mention) -> String {
+        match *mention {
+            Mention::User(id) => {
+                let member = self.members.iter().find(|member| member.user.id == id);
+                let name = match member {
+                    Some(member) => Some(util::display_name(member).to_string()),
+                    None => self.users.iter()
+                        .find(|user| user.id == id)
+                        .map(|user| user.member.as_ref().and_then(|member| member.nick.clone()).unwrap_or(user.name.clone())),
+                };
+                format!("@{}", name.unwrap_or("unknown-user".into()))
+            }
+            Mention::Channel(id) => {
+                let channel = self.channels.0.iter()
+                    .chain(&self.channels.1)
+                    .find(|channel| channel.id == id);
+                format!("#{}", channel.and_then(|channel| channel.name.as_deref()).unwrap_or("unknown-channel"))
+            }
+            Mention::Role(id) => {
+                let role = self.server.and_then(|server| server.role(id));
+                format!("@{}", role.map_or("deleted-role", |role| role.name.as_str()))
+            }
+            Mention::Timestamp(secs, style) => self.time.format(secs, style),
+            Mention::Emoji(..) => ":emoji:".into(),
+        }
+    }
+
+    // Text and background color of the pill
+    pub fn colors(&self, mention: &Mention, visuals: &Visuals) -> (Color32, Color32) {
+        match *mention {
+            Mention::Role(id) => {
+                let color = self.server
+                    .and_then(|server| server.role(id))
+                    .and_then(|role| util::role_color(role.color));
+                match color {
+                    Some(color) => (color, color.gamma_multiply(0.2)),
+                    None => (visuals.hyperlink_color, visuals.hyperlink_color.gamma_multiply(0.2)),
+                }
+            }
+            Mention::Timestamp(..) => (visuals.text_color(), visuals.code_bg_color),
+            _ => (visuals.hyperlink_color, visuals.hyperlink_color.gamma_multiply(0.2)),
+        }
+    }
+}
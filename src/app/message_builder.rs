@@ -0,0 +1,524 @@
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use egui::{Color32, Context, RichText, Vec2};
+use serde::{Deserialize, Serialize};
+use twilight_model::channel::message::component::{ActionRow, Button, ButtonStyle, SelectMenu, SelectMenuOption};
+use twilight_model::channel::message::embed::{EmbedAuthor, EmbedField, EmbedFooter, EmbedImage, EmbedThumbnail};
+use twilight_model::channel::message::{Component, Embed};
+use twilight_model::util::Timestamp;
+use twilight_validate::message as validate;
+use crate::app::DiscordApp;
+use crate::app::{embed, markdown};
+use crate::app::mentions::MentionContext;
+use crate::app::media::MediaGate;
+use crate::discord::jobs::{Job, SendMessage};
+
+const PRESETS_PATH: &str = "res/message_presets.json";
+const BUTTON_STYLES: [(&str, ButtonStyle); 5] = [
+    ("Primary", ButtonStyle::Primary),
+    ("Secondary", ButtonStyle::Secondary),
+    ("Success", ButtonStyle::Success),
+    ("Danger", ButtonStyle::Danger),
+    ("Link", ButtonStyle::Link),
+];
+const BUTTONS_PER_ROW: usize = 5;
+
+// Presets are edited by hand, styles out of range fall back to the first one
+fn button_style(index: usize) -> (&'static str, ButtonStyle) {
+    BUTTON_STYLES.get(index).copied().unwrap_or(BUTTON_STYLES[0])
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct FieldDraft {
+    name: String,
+    value: String,
+    inline: bool,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct EmbedDraft {
+    title: String,
+    url: String,
+    description: String,
+    color: Option<[u8; 3]>,
+    author: String,
+    footer: String,
+    image_url: String,
+    thumbnail_url: String,
+    timestamp: bool, // the time of sending
+    fields: Vec<FieldDraft>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ComponentDraft {
+    Button { label: String, style: usize, custom_id: String, url: String },
+    Select { custom_id: String, placeholder: String, options: Vec<(String, String)> }, // (label, value)
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct MessageDraft {
+    content: String,
+    embeds: Vec<EmbedDraft>,
+    components: Vec<ComponentDraft>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Preset {
+    name: String,
+    draft: MessageDraft,
+}
+
+pub struct MessageBuilder {
+    pub open: bool,
+    draft: MessageDraft,
+    presets: Option<Vec<Preset>>, // read when the builder is first opened
+    preset_name: String,
+    error: String,
+}
+
+fn optional(text: &str) -> Option<String> {
+    let trimmed = text.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+impl EmbedDraft {
+    fn is_empty(&self) -> bool {
+        self.title.trim().is_empty() && self.description.trim().is_empty() && self.fields.is_empty()
+            && self.image_url.trim().is_empty() && self.thumbnail_url.trim().is_empty()
+    }
+
+    fn build(&self, timestamp: Timestamp) -> Embed {
+        Embed {
+            author: optional(&self.author).map(|name| EmbedAuthor { icon_url: None, name, proxy_icon_url: None, url: None }),
+            color: self.color.map(|[r, g, b]| (r as u32) << 16 | (g as u32) << 8 | b as u32),
+            description: optional(&self.description),
+            fields: self.fields.iter().map(|field| EmbedField {
+                inline: field.inline,
+                name: field.name.clone(),
+                value: field.value.clone(),
+            }).collect(),
+            footer: optional(&self.footer).map(|text| EmbedFooter { icon_url: None, proxy_icon_url: None, text }),
+            image: optional(&self.image_url).map(|url| EmbedImage { height: None, proxy_url: None, url, width: None }),
+            kind: "rich".into(),
+            provider: None,
+            thumbnail: optional(&self.thumbnail_url).map(|url| EmbedThumbnail { height: None, proxy_url: None, url, width: None }),
+            timestamp: self.timestamp.then_some(timestamp),
+            title: optional(&self.title),
+            url: optional(&self.url),
+            video: None,
+        }
+    }
+}
+
+impl MessageDraft {
+    fn is_empty(&self) -> bool {
+        self.content.trim().is_empty() && self.embeds.iter().all(EmbedDraft::is_empty)
+    }
+
+    fn embeds(&self) -> Vec<Embed> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as i64);
+        let now = Timestamp::from_secs(now).expect("Current time is a valid timestamp");
+        self.embeds.iter()
+            .filter(|embed| !embed.is_empty())
+            .map(|embed| embed.build(now))
+            .collect()
+    }
+
+    // Buttons are packed into rows, each select menu takes a row of its own
+    fn components(&self) -> Vec<Component> {
+        let mut rows: Vec<Vec<Component>> = vec![];
+        let mut buttons = vec![];
+        for component in &self.components {
+            match component {
+                ComponentDraft::Button { label, style, custom_id, url } => {
+                    let style = button_style(*style).1;
+                    let link = style == ButtonStyle::Link;
+                    buttons.push(Component::Button(Button {
+                        custom_id: if link { None } else { optional(custom_id) },
+                        disabled: false,
+                        emoji: None,
+                        label: optional(label),
+                        style,
+                        url: if link { optional(url) } else { None },
+                    }));
+                    if buttons.len() == BUTTONS_PER_ROW {
+                        rows.push(std::mem::take(&mut buttons));
+                    }
+                }
+                ComponentDraft::Select { custom_id, placeholder, options } => {
+                    rows.push(vec![Component::SelectMenu(SelectMenu {
+                        custom_id: custom_id.trim().to_string(),
+                        disabled: false,
+                        max_values: None,
+                        min_values: None,
+                        options: options.iter().map(|(label, value)| SelectMenuOption {
+                            default: false,
+                            description: None,
+                            emoji: None,
+                            label: label.clone(),
+                            value: value.clone(),
+                        }).collect(),
+                        placeholder: optional(placeholder),
+                    })]);
+                }
+            }
+        }
+        if !buttons.is_empty() {
+            rows.push(buttons);
+        }
+        rows.into_iter()
+            .map(|components| Component::ActionRow(ActionRow { components }))
+            .collect()
+    }
+
+    // Checked with the same rules as the request, so limits are reported here instead of failing silently
+    fn validate(&self) -> Result<(Vec<Embed>, Vec<Component>), String> {
+        let embeds = self.embeds();
+        let components = self.components();
+        validate::content(&self.content).map_err(|err| err.to_string())?;
+        validate::embeds(&embeds).map_err(|err| err.to_string())?;
+        validate::components(&components).map_err(|err| err.to_string())?;
+        Ok((embeds, components))
+    }
+}
+
+impl MessageBuilder {
+    pub fn new() -> Self {
+        Self{
+            open: false,
+            draft: MessageDraft::default(),
+            presets: None,
+            preset_name: "".into(),
+            error: "".into(),
+        }
+    }
+
+    fn presets(&mut self) -> &mut Vec<Preset> {
+        self.presets.get_or_insert_with(|| {
+            fs::read_to_string(PRESETS_PATH).ok()
+                .and_then(|contents| serde_json::from_str(&contents).ok())
+                .unwrap_or_default()
+        })
+    }
+
+    fn save_preset(&mut self) {
+        let name = self.preset_name.trim().to_string();
+        if name.is_empty() {
+            self.error = "Preset needs a name".into();
+            return;
+        }
+        let draft = self.draft.clone();
+        let presets = self.presets();
+        match presets.iter_mut().find(|preset| preset.name == name) {
+            Some(preset) => preset.draft = draft,
+            None => presets.push(Preset { name, draft }),
+        }
+        let json = serde_json::to_string_pretty(self.presets()).expect("Presets are serializable");
+        self.error = match fs::write(PRESETS_PATH, json) {
+            Ok(_) => "".into(),
+            Err(err) => format!("Couldn't save presets: {}", err),
+        };
+    }
+}
+
+impl DiscordApp {
+    pub fn message_builder_window(&mut self, ctx: &Context) {
+        if !self.message_builder.open {
+            return;
+        }
+        let mut open = true;
+        let mut send = false;
+        egui::Window::new("Message builder")
+            .id(egui::Id::new("message_builder"))
+            .open(&mut open)
+            .default_size(Vec2::new(800.0, 500.0))
+            .resizable(true)
+            .show(ctx, |ui| {
+                let builder = &mut self.message_builder;
+                presets_bar(ui, builder);
+                ui.separator();
+                ui.columns(2, |columns| {
+                    egui::ScrollArea::vertical()
+                        .id_source("builder_editor")
+                        .show(&mut columns[0], |ui| draft_editor(ui, &mut builder.draft));
+                    egui::ScrollArea::vertical()
+                        .id_source("builder_preview")
+                        .show(&mut columns[1], |ui| {
+                            ui.strong("Preview");
+                            let servers = self.cache.servers.get();
+                            let members = self.cache.members.get();
+                            let channels = self.cache.channels.get();
+                            let context = MentionContext {
+                                server: servers.iter().find(|server| server.id == self.selected_server_id),
+                                members: &members,
+                                channels: &channels,
+                                users: &[],
+                                time: &self.time,
+                            };
+                            // the draft's own images are shown regardless of the policy
+                            let gate = MediaGate::new(&self.config.media, &self.shared_cache.loaded_media)
+                                .load_all(true)
+                                .spoilers(self.config.spoilers, false);
+                            preview(ui, &builder.draft, &context, &gate);
+                        });
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let can_send = self.selected_channel_id != 0 && !builder.draft.is_empty();
+                    if ui.add_enabled(can_send, egui::Button::new("Send")).clicked() {
+                        send = true;
+                    }
+                    if ui.button("Clear").clicked() {
+                        builder.draft = MessageDraft::default();
+                    }
+                    if !builder.error.is_empty() {
+                        ui.colored_label(ui.visuals().error_fg_color, &builder.error);
+                    }
+                });
+            });
+        if send {
+            let draft = &self.message_builder.draft;
+            match draft.validate() {
+                Ok((embeds, components)) => {
+                    let reply_id = (self.reply_message_id != 0).then_some(self.reply_message_id);
+                    let mut job = SendMessage::new(self.selected_channel_id, draft.content.clone(), reply_id);
+                    job.embeds = embeds;
+                    job.components = components;
+                    self.reply_message_id = 0;
+                    self.message_builder.error.clear();
+                    self.append_job(Job::SendMessage(job));
+                }
+                Err(err) => self.message_builder.error = format!("Can't send: {}", err),
+            }
+        }
+        if !open {
+            self.message_builder.open = false;
+        }
+    }
+}
+
+fn presets_bar(ui: &mut egui::Ui, builder: &mut MessageBuilder) {
+    ui.horizontal(|ui| {
+        let mut loaded = None;
+        egui::ComboBox::from_id_source("builder_presets")
+            .selected_text("Load preset")
+            .show_ui(ui, |ui| {
+                for preset in builder.presets().iter() {
+                    if ui.selectable_label(false, &preset.name).clicked() {
+                        loaded = Some((preset.name.clone(), preset.draft.clone()));
+                    }
+                }
+            });
+        if let Some((name, draft)) = loaded {
+            builder.preset_name = name;
+            builder.draft = draft;
+        }
+        ui.add(egui::TextEdit::singleline(&mut builder.preset_name)
+            .desired_width(150.0)
+            .hint_text("Preset name"));
+        if ui.button("Save preset").clicked() {
+            builder.save_preset();
+        }
+    });
+}
+
+fn draft_editor(ui: &mut egui::Ui, draft: &mut MessageDraft) {
+    ui.label("Content");
+    ui.add(egui::TextEdit::multiline(&mut draft.content).desired_rows(2).desired_width(f32::INFINITY));
+    let mut removed_embed = None;
+    for (index, embed) in draft.embeds.iter_mut().enumerate() {
+        egui::CollapsingHeader::new(format!("Embed {}", index + 1))
+            .id_source(("builder_embed", index))
+            .default_open(true)
+            .show(ui, |ui| {
+                embed_editor(ui, index, embed);
+                if ui.button("Remove embed").clicked() {
+                    removed_embed = Some(index);
+                }
+            });
+    }
+    if let Some(index) = removed_embed {
+        draft.embeds.remove(index);
+    }
+    // Discord allows 10 embeds per message
+    if draft.embeds.len() < 10 && ui.button("Add embed").clicked() {
+        draft.embeds.push(EmbedDraft::default());
+    }
+    ui.separator();
+    ui.strong("Components");
+    let mut removed_component = None;
+    for (index, component) in draft.components.iter_mut().enumerate() {
+        ui.push_id(("builder_component", index), |ui| {
+            ui.horizontal_top(|ui| {
+                if ui.small_button("✖").clicked() {
+                    removed_component = Some(index);
+                }
+                ui.vertical(|ui| component_editor(ui, component));
+            });
+        });
+    }
+    if let Some(index) = removed_component {
+        draft.components.remove(index);
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Add button").clicked() {
+            draft.components.push(ComponentDraft::Button {
+                label: "Button".into(), style: 0, custom_id: "button".into(), url: "".into(),
+            });
+        }
+        if ui.button("Add select menu").clicked() {
+            draft.components.push(ComponentDraft::Select {
+                custom_id: "select".into(), placeholder: "".into(), options: vec![("Option".into(), "option".into())],
+            });
+        }
+    });
+}
+
+fn embed_editor(ui: &mut egui::Ui, index: usize, embed: &mut EmbedDraft) {
+    egui::Grid::new(("embed_editor", index)).num_columns(2).show(ui, |ui| {
+        let lines = [
+            ("Author", &mut embed.author),
+            ("Title", &mut embed.title),
+            ("Title URL", &mut embed.url),
+            ("Footer", &mut embed.footer),
+            ("Image URL", &mut embed.image_url),
+            ("Thumbnail URL", &mut embed.thumbnail_url),
+        ];
+        for (label, text) in lines {
+            ui.label(label);
+            ui.text_edit_singleline(text);
+            ui.end_row();
+        }
+        ui.label("Color");
+        ui.horizontal(|ui| {
+            let mut colored = embed.color.is_some();
+            ui.checkbox(&mut colored, "");
+            match (colored, &mut embed.color) {
+                (true, Some(color)) => {
+                    ui.color_edit_button_srgb(color);
+                }
+                (true, None) => embed.color = Some([88, 101, 242]),
+                (false, _) => embed.color = None,
+            }
+        });
+        ui.end_row();
+        ui.label("Timestamp");
+        ui.checkbox(&mut embed.timestamp, "time of sending");
+        ui.end_row();
+    });
+    ui.label("Description");
+    ui.add(egui::TextEdit::multiline(&mut embed.description).desired_rows(3).desired_width(f32::INFINITY));
+    let mut removed = None;
+    for (field_index, field) in embed.fields.iter_mut().enumerate() {
+        ui.push_id(("embed_field", index, field_index), |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut field.name).desired_width(100.0).hint_text("Name"));
+                ui.add(egui::TextEdit::singleline(&mut field.value).desired_width(150.0).hint_text("Value"));
+                ui.checkbox(&mut field.inline, "inline");
+                if ui.small_button("✖").clicked() {
+                    removed = Some(field_index);
+                }
+            });
+        });
+    }
+    if let Some(field_index) = removed {
+        embed.fields.remove(field_index);
+    }
+    // and 25 fields per embed
+    if embed.fields.len() < 25 && ui.button("Add field").clicked() {
+        embed.fields.push(FieldDraft::default());
+    }
+}
+
+fn component_editor(ui: &mut egui::Ui, component: &mut ComponentDraft) {
+    match component {
+        ComponentDraft::Button { label, style, custom_id, url } => {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(label).desired_width(100.0).hint_text("Label"));
+                egui::ComboBox::from_id_source("button_style")
+                    .selected_text(button_style(*style).0)
+                    .show_ui(ui, |ui| {
+                        for (index, (name, _)) in BUTTON_STYLES.iter().enumerate() {
+                            ui.selectable_value(style, index, *name);
+                        }
+                    });
+            });
+            // link buttons carry a url instead of a custom id
+            if button_style(*style).1 == ButtonStyle::Link {
+                ui.add(egui::TextEdit::singleline(url).hint_text("URL"));
+            } else {
+                ui.add(egui::TextEdit::singleline(custom_id).hint_text("Custom ID"));
+            }
+        }
+        ComponentDraft::Select { custom_id, placeholder, options } => {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(custom_id).desired_width(100.0).hint_text("Custom ID"));
+                ui.add(egui::TextEdit::singleline(placeholder).desired_width(120.0).hint_text("Placeholder"));
+            });
+            let mut removed = None;
+            for (index, (label, value)) in options.iter_mut().enumerate() {
+                ui.push_id(index, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(label).desired_width(100.0).hint_text("Label"));
+                        ui.add(egui::TextEdit::singleline(value).desired_width(100.0).hint_text("Value"));
+                        if ui.small_button("✖").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                });
+            }
+            if let Some(index) = removed {
+                options.remove(index);
+            }
+            if options.len() < 25 && ui.small_button("Add option").clicked() {
+                options.push(("".into(), "".into()));
+            }
+        }
+    }
+}
+
+fn preview(ui: &mut egui::Ui, draft: &MessageDraft, context: &MentionContext, gate: &MediaGate) {
+    if !draft.content.trim().is_empty() {
+        let layout = markdown::layout_job(ui.ctx(), ui.style(), &draft.content, context);
+        markdown::show(ui, layout, gate.reveal_mode());
+    }
+    for embed in draft.embeds() {
+        embed::show_embed(ui, &embed, gate, context);
+        ui.add_space(4.0);
+    }
+    for row in draft.components() {
+        let Component::ActionRow(row) = row else {
+            continue;
+        };
+        ui.horizontal_wrapped(|ui| {
+            for component in &row.components {
+                match component {
+                    Component::Button(button) => {
+                        let text = RichText::new(button.label.as_deref().unwrap_or("")).color(Color32::WHITE);
+                        let fill = match button.style {
+                            ButtonStyle::Primary => Color32::from_rgb(88, 101, 242),
+                            ButtonStyle::Success => Color32::from_rgb(36, 128, 70),
+                            ButtonStyle::Danger => Color32::from_rgb(218, 55, 60),
+                            _ => Color32::from_gray(78),
+                        };
+                        let response = ui.add(egui::Button::new(text).fill(fill));
+                        if let Some(url) = &button.url {
+                            response.on_hover_text(url);
+                        }
+                    }
+                    Component::SelectMenu(menu) => {
+                        egui::ComboBox::from_id_source(("preview_select", &menu.custom_id))
+                            .selected_text(menu.placeholder.as_deref().unwrap_or("Make a selection"))
+                            .width(250.0)
+                            .show_ui(ui, |ui| {
+                                for option in &menu.options {
+                                    ui.label(&option.label);
+                                }
+                            });
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,75 @@
+use egui::{FontId, Label, Rect, Response, RichText, Sense, Ui, Vec2};
+use egui::ImageSource::Uri;
+use twilight_model::channel::Message;
+use twilight_model::channel::message::MessageType;
+use twilight_model::guild::Member;
+use crate::discord::guild::Server;
+use crate::discord::util;
+use crate::time_format::TimeFormat;
+
+// Consecutive messages of one author are drawn under a single header with the avatar, name and time
+
+pub const AVATAR_SIZE: f32 = 32.0;
+const GROUP_WINDOW_SECS: i64 = 7 * 60;
+
+// Replies always get their own header
+pub fn starts_group(previous: Option<&Message>, msg: &Message, time: &TimeFormat) -> bool {
+    let Some(previous) = previous else {
+        return true;
+    };
+    let (before, now) = (previous.timestamp.as_secs(), msg.timestamp.as_secs());
+    previous.author.id != msg.author.id
+        || msg.kind == MessageType::Reply
+        || now - before > GROUP_WINDOW_SECS
+        || !time.same_day(before, now)
+}
+
+// A line across the chat with the date over its middle
+pub fn date_divider(ui: &mut Ui, secs: i64, time: &TimeFormat) {
+    let (rect, _) = ui.allocate_exact_size(Vec2::new(ui.available_width(), 20.0), Sense::hover());
+    let painter = ui.painter();
+    painter.hline(rect.x_range(), rect.center().y, ui.visuals().widgets.noninteractive.bg_stroke);
+    let date = time.format(secs, 'D');
+    let text = painter.layout_no_wrap(date, FontId::proportional(11.0), ui.visuals().weak_text_color());
+    let label = Rect::from_center_size(rect.center(), text.size() + Vec2::new(12.0, 0.0));
+    painter.rect_filled(label, 0.0, ui.visuals().panel_fill);
+    painter.galley(label.min + Vec2::new(6.0, 0.0), text);
+}
+
+pub fn avatar(ui: &mut Ui, msg: &Message, member: Option<&Member>, server_id: u64) -> Response {
+    let url = match member {
+        Some(member) => util::avatar_url(member, server_id, 64),
+        None => util::user_avatar_url(&msg.author, 64),
+    };
+    ui.add(egui::Image::new(Uri(url.into()))
+        .fit_to_exact_size(Vec2::splat(AVATAR_SIZE))
+        .rounding(AVATAR_SIZE / 2.0)
+        .sense(Sense::click()))
+}
+
+// The name in the author's role color, followed by the time
+pub fn author_line(ui: &mut Ui, msg: &Message, member: Option<&Member>, server: Option<&Server>, time: &TimeFormat) -> Response {
+    ui.horizontal(|ui| {
+        let name = match member {
+            Some(member) => util::display_name(member),
+            None => msg.author.global_name.as_deref().unwrap_or(&msg.author.name),
+        };
+        let mut name = RichText::new(name).strong();
+        let color = member.zip(server).and_then(|(member, server)| util::role_color(server.member_color(member)));
+        if let Some(color) = color {
+            name = name.color(color);
+        }
+        let response = ui.add(Label::new(name).sense(Sense::click()));
+        let secs = msg.timestamp.as_secs();
+        ui.label(RichText::new(time.datetime(secs)).small().weak())
+            .on_hover_text(time.format(secs, 'F'));
+        response
+    }).inner
+}
+
+pub fn edited_marker(ui: &mut Ui, msg: &Message, time: &TimeFormat) {
+    if let Some(edited) = msg.edited_timestamp {
+        ui.label(RichText::new("(edited)").small().weak())
+            .on_hover_text(time.format(edited.as_secs(), 'F'));
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use twilight_model::channel::Message;
+
+// Heights of laid out messages, so the chat only lays out what's in view and skips the rest.
+// Entries are keyed on what changes the layout: edits, the header above and the panel width.
+
+pub const ESTIMATED_HEIGHT: f32 = 40.0; // for messages that haven't been shown yet
+
+#[derive(Default)]
+pub struct MessageHeights {
+    heights: HashMap<u64, (u64, f32)>, // by message id, the layout key and height
+}
+
+impl MessageHeights {
+    pub fn get(&self, message_id: u64, key: u64) -> Option<f32> {
+        self.heights.get(&message_id)
+            .filter(|(cached_key, _)| *cached_key == key)
+            .map(|(_, height)| *height)
+    }
+
+    pub fn insert(&mut self, message_id: u64, key: u64, height: f32) {
+        self.heights.insert(message_id, (key, height));
+    }
+
+    pub fn clear(&mut self) {
+        self.heights.clear();
+    }
+}
+
+pub fn layout_key(msg: &Message, header: bool, divider: bool, width: f32) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    msg.edited_timestamp.map(|edited| edited.as_micros()).hash(&mut hasher);
+    msg.content.len().hash(&mut hasher);
+    (header, divider, width.round() as i32).hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,311 @@
+use egui::{Context, Vec2};
+use twilight_model::guild::audit_log::AuditLogEventType;
+use twilight_model::id::{marker::UserMarker, Id};
+use twilight_util::snowflake::Snowflake;
+use twilight_validate::request::{audit_reason, AUDIT_REASON_MAX};
+use crate::app::DiscordApp;
+use crate::discord::jobs::{BanMember, GetAuditLog, GetBans, Job, KickMember, TimeoutMember, UnbanMember};
+
+const TIMEOUT_DURATIONS: [(&str, u64); 6] = [
+    ("60 seconds", 60),
+    ("5 minutes", 5 * 60),
+    ("10 minutes", 10 * 60),
+    ("1 hour", 60 * 60),
+    ("1 day", 24 * 60 * 60),
+    ("1 week", 7 * 24 * 60 * 60),
+];
+
+const AUDIT_LOG_FILTERS: [AuditLogEventType; 18] = [
+    AuditLogEventType::GuildUpdate,
+    AuditLogEventType::ChannelCreate,
+    AuditLogEventType::ChannelUpdate,
+    AuditLogEventType::ChannelDelete,
+    AuditLogEventType::ChannelOverwriteUpdate,
+    AuditLogEventType::MemberKick,
+    AuditLogEventType::MemberPrune,
+    AuditLogEventType::MemberBanAdd,
+    AuditLogEventType::MemberBanRemove,
+    AuditLogEventType::MemberUpdate,
+    AuditLogEventType::MemberRoleUpdate,
+    AuditLogEventType::BotAdd,
+    AuditLogEventType::RoleCreate,
+    AuditLogEventType::RoleUpdate,
+    AuditLogEventType::RoleDelete,
+    AuditLogEventType::MessageDelete,
+    AuditLogEventType::MessageBulkDelete,
+    AuditLogEventType::MessagePin,
+];
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum ModAction {
+    Kick,
+    Ban,
+    Timeout,
+    Unban,
+}
+
+pub struct ModerationDialog {
+    action: ModAction,
+    server_id: u64, // the server it was opened for, not whichever is selected on confirm
+    user_id: u64,
+    user_name: String,
+    reason: String,
+    delete_message_days: u8,
+    timeout_secs: u64,
+    error: String,
+}
+
+impl ModerationDialog {
+    pub fn new(action: ModAction, server_id: u64, user_id: u64, user_name: String) -> Self {
+        Self{
+            action,
+            server_id,
+            user_id,
+            user_name,
+            reason: "".into(),
+            delete_message_days: 0,
+            timeout_secs: TIMEOUT_DURATIONS[0].1,
+            error: "".into(),
+        }
+    }
+
+    fn job(&self) -> Result<Job, String> {
+        let server_id = self.server_id;
+        let reason = self.reason.trim().to_string();
+        audit_reason(&reason).map_err(|err| err.to_string())?;
+        Ok(match self.action {
+            ModAction::Kick => Job::KickMember(KickMember::new(server_id, self.user_id, reason)),
+            ModAction::Ban => Job::BanMember(BanMember::new(server_id, self.user_id, self.delete_message_days, reason)),
+            ModAction::Timeout => Job::TimeoutMember(TimeoutMember::new(server_id, self.user_id, self.timeout_secs, reason)),
+            ModAction::Unban => Job::UnbanMember(UnbanMember::new(server_id, self.user_id, reason)),
+        })
+    }
+}
+
+pub struct Moderation {
+    pub dialog: Option<ModerationDialog>,
+    pub bans_open: bool,
+    pub audit_log_open: bool,
+    audit_action: Option<AuditLogEventType>,
+    audit_user_id: String,
+    audit_error: String,
+}
+
+impl Moderation {
+    pub fn new() -> Self {
+        Self{
+            dialog: None,
+            bans_open: false,
+            audit_log_open: false,
+            audit_action: None,
+            audit_user_id: "".into(),
+            audit_error: "".into(),
+        }
+    }
+}
+
+impl DiscordApp {
+    pub fn moderation_windows(&mut self, ctx: &Context) {
+        self.moderation_dialog(ctx);
+        self.bans_window(ctx);
+        self.audit_log_window(ctx);
+    }
+
+    fn moderation_dialog(&mut self, ctx: &Context) {
+        let Some(dialog) = &mut self.moderation.dialog else {
+            return;
+        };
+        let title = match dialog.action {
+            ModAction::Kick => "Kick member",
+            ModAction::Ban => "Ban member",
+            ModAction::Timeout => "Timeout member",
+            ModAction::Unban => "Unban user",
+        };
+        let mut open = true;
+        let mut close = false;
+        let mut job = None;
+        egui::Window::new(title)
+            .id(egui::Id::new("moderation_dialog"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("User: {} ({})", dialog.user_name, dialog.user_id));
+                match dialog.action {
+                    ModAction::Ban => {
+                        ui.horizontal(|ui| {
+                            ui.label("Delete messages from the last");
+                            ui.add(egui::DragValue::new(&mut dialog.delete_message_days).clamp_range(0..=7));
+                            ui.label("days");
+                        });
+                    }
+                    ModAction::Timeout => {
+                        let selected = TIMEOUT_DURATIONS.iter()
+                            .find(|(_, secs)| *secs == dialog.timeout_secs)
+                            .map(|(label, _)| *label)
+                            .unwrap_or("Remove timeout");
+                        egui::ComboBox::from_label("Duration")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                for (label, secs) in TIMEOUT_DURATIONS {
+                                    ui.selectable_value(&mut dialog.timeout_secs, secs, label);
+                                }
+                                ui.selectable_value(&mut dialog.timeout_secs, 0, "Remove timeout");
+                            });
+                    }
+                    _ => {}
+                }
+                ui.add(egui::TextEdit::multiline(&mut dialog.reason)
+                    .desired_rows(2)
+                    .char_limit(AUDIT_REASON_MAX)
+                    .hint_text("Audit log reason (optional)"));
+                if !dialog.error.is_empty() {
+                    ui.colored_label(ui.visuals().error_fg_color, &dialog.error);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Confirm").clicked() {
+                        match dialog.job() {
+                            Ok(confirmed) => {
+                                job = Some(confirmed);
+                                close = true;
+                            }
+                            Err(err) => dialog.error = err,
+                        }
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        if let Some(job) = job {
+            self.append_job(job);
+        }
+        if !open || close {
+            self.moderation.dialog = None;
+        }
+    }
+
+    fn bans_window(&mut self, ctx: &Context) {
+        let mut open = self.moderation.bans_open;
+        let mut unban = None;
+        egui::Window::new(format!("Bans - {}", self.current_server))
+            .id(egui::Id::new("bans_window"))
+            .open(&mut open)
+            .default_size(Vec2::new(300.0, 300.0))
+            .show(ctx, |ui| {
+                if ui.button("Refresh").clicked() && self.selected_server_id != 0 {
+                    self.append_job(Job::GetBans(GetBans::new(self.selected_server_id)));
+                }
+                ui.separator();
+                egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+                    let bans = self.cache.bans.get();
+                    let (server_id, bans) = &*bans;
+                    // fetched for another server, the ids mean nothing here
+                    let bans = if *server_id == self.selected_server_id { bans.as_slice() } else { &[] };
+                    if bans.is_empty() {
+                        ui.label("No bans");
+                    }
+                    for ban in bans {
+                        ui.horizontal(|ui| {
+                            ui.label(&ban.user.name);
+                            if let Some(reason) = &ban.reason {
+                                ui.weak(reason);
+                            }
+                            if ui.small_button("Unban").clicked() {
+                                unban = Some(ModerationDialog::new(ModAction::Unban, *server_id, ban.user.id.get(), ban.user.name.clone()));
+                            }
+                        });
+                    }
+                });
+            });
+        if unban.is_some() {
+            self.moderation.dialog = unban;
+        }
+        self.moderation.bans_open = open;
+    }
+
+    fn audit_log_window(&mut self, ctx: &Context) {
+        let mut open = self.moderation.audit_log_open;
+        let mut fetch = None;
+        egui::Window::new(format!("Audit log - {}", self.current_server))
+            .id(egui::Id::new("audit_log_window"))
+            .open(&mut open)
+            .default_size(Vec2::new(500.0, 400.0))
+            .show(ctx, |ui| {
+                let moderation = &mut self.moderation;
+                ui.horizontal(|ui| {
+                    let selected = match moderation.audit_action {
+                        Some(action) => format!("{:?}", action),
+                        None => "All actions".into(),
+                    };
+                    egui::ComboBox::from_id_source("audit_log_action")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut moderation.audit_action, None, "All actions");
+                            for action in AUDIT_LOG_FILTERS {
+                                ui.selectable_value(&mut moderation.audit_action, Some(action), format!("{:?}", action));
+                            }
+                        });
+                    ui.add(egui::TextEdit::singleline(&mut moderation.audit_user_id)
+                        .desired_width(140.0)
+                        .hint_text("User ID"));
+                    if ui.button("Fetch").clicked() && self.selected_server_id != 0 {
+                        // an empty filter means everyone, anything else has to be a valid id
+                        let user_id = moderation.audit_user_id.trim();
+                        let parsed = user_id.parse::<u64>().ok().and_then(Id::<UserMarker>::new_checked);
+                        if user_id.is_empty() || parsed.is_some() {
+                            moderation.audit_error.clear();
+                            let user_id = parsed.map(|id| id.get());
+                            fetch = Some(GetAuditLog::new(self.selected_server_id, moderation.audit_action, user_id, 100));
+                        } else {
+                            moderation.audit_error = format!("\"{}\" is not a user id", user_id);
+                        }
+                    }
+                });
+                if !moderation.audit_error.is_empty() {
+                    ui.colored_label(ui.visuals().error_fg_color, &moderation.audit_error);
+                }
+                ui.separator();
+                let audit_log = self.cache.audit_log.get();
+                let audit_log = match &*audit_log {
+                    Some((server_id, audit_log)) if *server_id == self.selected_server_id => audit_log,
+                    _ => {
+                        ui.label("Nothing fetched yet");
+                        return;
+                    }
+                };
+                egui::ScrollArea::both().auto_shrink([false, false]).show(ui, |ui| {
+                    egui::Grid::new("audit_log_entries").striped(true).num_columns(5).show(ui, |ui| {
+                        ui.strong("Date");
+                        ui.strong("Action");
+                        ui.strong("User");
+                        ui.strong("Target");
+                        ui.strong("Reason");
+                        ui.end_row();
+                        for entry in &audit_log.entries {
+                            let user_name = entry.user_id
+                                .and_then(|id| audit_log.users.iter().find(|user| user.id == id))
+                                .map(|user| user.name.clone())
+                                .unwrap_or_default();
+                            let target = entry.target_id
+                                .map(|id| {
+                                    let user = audit_log.users.iter().find(|user| user.id == id.get());
+                                    user.map(|user| user.name.clone()).unwrap_or(id.to_string())
+                                })
+                                .unwrap_or_default();
+                            ui.label(self.time.datetime_millis(entry.id.timestamp()));
+                            ui.label(format!("{:?}", entry.action_type));
+                            ui.label(user_name);
+                            ui.label(target);
+                            ui.label(entry.reason.as_deref().unwrap_or(""));
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        if let Some(fetch) = fetch {
+            self.append_job(Job::GetAuditLog(fetch));
+        }
+        self.moderation.audit_log_open = open;
+    }
+}
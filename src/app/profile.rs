@@ -0,0 +1,203 @@
+use egui::{Color32, Context, RichText, Vec2};
+use egui::ImageSource::Uri;
+use twilight_model::channel::{message, Message};
+use twilight_model::guild::Member;
+use twilight_model::id::Id;
+use twilight_model::id::marker::RoleMarker;
+use twilight_model::user::User;
+use twilight_model::util::Timestamp;
+use twilight_util::snowflake::Snowflake;
+use crate::app::DiscordApp;
+use crate::app::moderation::{ModAction, ModerationDialog};
+use crate::discord::jobs::{GetUser, Job};
+use crate::discord::util;
+
+const BANNER_SIZE: Vec2 = Vec2::new(300.0, 105.0);
+const AVATAR_SIZE: f32 = 64.0;
+
+pub struct Profile {
+    pub user: Option<User>, // None while closed
+    nick: Option<String>,
+    roles: Vec<Id<RoleMarker>>,
+    joined_at: Option<Timestamp>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self{
+            user: None,
+            nick: None,
+            roles: vec![],
+            joined_at: None,
+        }
+    }
+
+    pub fn from_member(member: &Member) -> Self {
+        Self{
+            user: Some(member.user.clone()),
+            nick: member.nick.clone(),
+            roles: member.roles.clone(),
+            joined_at: Some(member.joined_at),
+        }
+    }
+
+    pub fn from_mention(mention: &message::Mention) -> Self {
+        let user = User {
+            accent_color: None,
+            avatar: mention.avatar,
+            avatar_decoration: None,
+            banner: None,
+            bot: mention.bot,
+            discriminator: mention.discriminator,
+            email: None,
+            flags: None,
+            global_name: None,
+            id: mention.id,
+            locale: None,
+            mfa_enabled: None,
+            name: mention.name.clone(),
+            premium_type: None,
+            public_flags: Some(mention.public_flags),
+            system: None,
+            verified: None,
+        };
+        let mut profile = Self::new();
+        profile.user = Some(user);
+        if let Some(member) = &mention.member {
+            profile.nick = member.nick.clone();
+            profile.roles = member.roles.clone();
+            profile.joined_at = Some(member.joined_at);
+        }
+        profile
+    }
+
+    // Guild messages carry a partial member of the author
+    pub fn from_author(msg: &Message) -> Self {
+        let mut profile = Self::new();
+        profile.user = Some(msg.author.clone());
+        if let Some(member) = &msg.member {
+            profile.nick = member.nick.clone();
+            profile.roles = member.roles.clone();
+            profile.joined_at = Some(member.joined_at);
+        }
+        profile
+    }
+}
+
+impl DiscordApp {
+    // The full user is fetched for the banner
+    pub fn open_profile(&mut self, profile: Profile) {
+        let Some(user) = &profile.user else {
+            return;
+        };
+        self.append_job(Job::GetUser(GetUser::new(user.id.get())));
+        self.profile = profile;
+    }
+
+    pub fn profile_window(&mut self, ctx: &Context) {
+        let Some(user) = &self.profile.user else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Profile")
+            .id(egui::Id::new("profile_window"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .default_width(BANNER_SIZE.x)
+            .show(ctx, |ui| {
+                let profile = &self.profile;
+                let servers = self.cache.servers.get();
+                let server = servers.iter().find(|server| server.id == self.selected_server_id);
+                // the member list is fresher than what the profile was opened with
+                let members = self.cache.members.get();
+                let member = members.iter().find(|member| member.user.id == user.id);
+                let nick = member.map_or(profile.nick.as_deref(), |member| member.nick.as_deref());
+                let roles = member.map_or(&profile.roles, |member| &member.roles);
+                let joined_at = member.map(|member| member.joined_at).or(profile.joined_at);
+
+                let fetched = self.cache.user_profile.get();
+                let full_user = (*fetched).as_ref().filter(|fetched| fetched.id == user.id);
+                match full_user.and_then(|full_user| util::banner_url(full_user, 600)) {
+                    Some(banner) => {
+                        ui.add(egui::Image::new(Uri(banner.into()))
+                            .fit_to_exact_size(BANNER_SIZE)
+                            .rounding(5.0));
+                    }
+                    None => {
+                        let accent = full_user
+                            .and_then(|full_user| full_user.accent_color)
+                            .and_then(util::role_color)
+                            .unwrap_or(Color32::from_gray(60));
+                        let (rect, _) = ui.allocate_exact_size(Vec2::new(BANNER_SIZE.x, BANNER_SIZE.y / 2.0), egui::Sense::hover());
+                        ui.painter().rect_filled(rect, 5.0, accent);
+                    }
+                }
+                ui.horizontal(|ui| {
+                    let avatar = match member {
+                        Some(member) => util::avatar_url(member, self.selected_server_id, 128),
+                        None => util::user_avatar_url(user, 128),
+                    };
+                    ui.add(egui::Image::new(Uri(avatar.into()))
+                        .fit_to_exact_size(Vec2::splat(AVATAR_SIZE))
+                        .rounding(AVATAR_SIZE / 2.0));
+                    ui.vertical(|ui| {
+                        let display_name = nick.or(user.global_name.as_deref()).unwrap_or(&user.name);
+                        ui.heading(display_name);
+                        ui.weak(format!("@{}", user.name));
+                        if user.bot {
+                            ui.small("BOT");
+                        }
+                    });
+                });
+                ui.separator();
+                egui::Grid::new("profile_details").num_columns(2).show(ui, |ui| {
+                    ui.label("Global name");
+                    ui.label(user.global_name.as_deref().unwrap_or("-"));
+                    ui.end_row();
+                    ui.label("Server nickname");
+                    ui.label(nick.unwrap_or("-"));
+                    ui.end_row();
+                    ui.label("Account created");
+                    ui.label(self.time.datetime_millis(user.id.timestamp()));
+                    ui.end_row();
+                    if let Some(joined_at) = joined_at {
+                        ui.label("Joined server");
+                        ui.label(self.time.datetime(joined_at.as_secs()));
+                        ui.end_row();
+                    }
+                    ui.label("ID");
+                    ui.label(user.id.to_string());
+                    ui.end_row();
+                });
+                let Some(server) = server else {
+                    return;
+                };
+                if !roles.is_empty() {
+                    ui.separator();
+                    ui.horizontal_wrapped(|ui| {
+                        for role in server.roles.iter().filter(|role| roles.contains(&role.id)) {
+                            let color = util::role_color(role.color).unwrap_or(ui.visuals().text_color());
+                            ui.label(RichText::new(format!("● {}", role.name)).color(color));
+                        }
+                    });
+                }
+                // without a join date the user isn't known to be a member
+                if joined_at.is_none() {
+                    return;
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let actions = [("Kick", ModAction::Kick), ("Ban", ModAction::Ban), ("Timeout", ModAction::Timeout)];
+                    for (label, action) in actions {
+                        if ui.button(label).clicked() {
+                            self.moderation.dialog = Some(ModerationDialog::new(action, server.id, user.id.get(), user.name.clone()));
+                        }
+                    }
+                });
+            });
+        if !open {
+            self.profile.user = None;
+        }
+    }
+}
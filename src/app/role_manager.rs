@@ -0,0 +1,171 @@
+use egui::{Context, RichText, Vec2};
+use twilight_model::guild::{Permissions, Role};
+use crate::app::DiscordApp;
+use crate::discord::guild::PERMISSION_NAMES;
+use crate::discord::jobs::{CreateRole, DeleteRole, EditRole, Job};
+use crate::discord::util;
+
+pub struct RoleManager {
+    pub open: bool,
+    selected_role: Option<u64>, // None while creating a new role
+    name: String,
+    color: [u8; 3],
+    permissions: Permissions,
+    hoist: bool,
+    mentionable: bool,
+}
+
+impl RoleManager {
+    pub fn new() -> Self {
+        Self{
+            open: false,
+            selected_role: None,
+            name: "new role".into(),
+            color: [0, 0, 0],
+            permissions: Permissions::empty(),
+            hoist: false,
+            mentionable: false,
+        }
+    }
+
+    fn select(&mut self, role: &Role) {
+        self.selected_role = Some(role.id.get());
+        self.name = role.name.clone();
+        self.color = [(role.color >> 16) as u8, (role.color >> 8) as u8, role.color as u8];
+        self.permissions = role.permissions;
+        self.hoist = role.hoist;
+        self.mentionable = role.mentionable;
+    }
+
+    fn color_value(&self) -> u32 {
+        let [r, g, b] = self.color;
+        (r as u32) << 16 | (g as u32) << 8 | b as u32
+    }
+}
+
+impl DiscordApp {
+    pub fn role_manager_window(&mut self, ctx: &Context) {
+        if !self.role_manager.open {
+            return;
+        }
+        let mut open = true;
+        let mut job = None;
+        egui::Window::new(format!("Roles - {}", self.current_server))
+            .id(egui::Id::new("role_manager"))
+            .open(&mut open)
+            .default_size(Vec2::new(450.0, 400.0))
+            .resizable(true)
+            .show(ctx, |ui| {
+                let manager = &mut self.role_manager;
+                let server_id = self.selected_server_id;
+                let servers = self.cache.servers.get();
+                let Some(server) = servers.iter().find(|server| server.id == server_id) else {
+                    ui.label("No server selected");
+                    return;
+                };
+                let members = self.cache.members.get();
+                ui.horizontal_top(|ui| {
+                    ui.vertical(|ui| {
+                        if ui.button("New role").clicked() {
+                            *manager = RoleManager::new();
+                            manager.open = true;
+                        }
+                        egui::ScrollArea::vertical()
+                            .id_source("role_list")
+                            .max_height(350.0)
+                            .show(ui, |ui| {
+                                for role in &server.roles {
+                                    let count = if role.id == server_id {
+                                        members.len()
+                                    } else {
+                                        members.iter().filter(|member| member.roles.contains(&role.id)).count()
+                                    };
+                                    let mut text = RichText::new(format!("{} ({})", role.name, count));
+                                    if let Some(color) = util::role_color(role.color) {
+                                        text = text.color(color);
+                                    }
+                                    let selected = manager.selected_role == Some(role.id.get());
+                                    if ui.selectable_label(selected, text).clicked() {
+                                        manager.select(role);
+                                    }
+                                }
+                            });
+                        ui.small("Counts include fetched members only");
+                    });
+                    ui.separator();
+                    ui.vertical(|ui| {
+                        let editable = match manager.selected_role.and_then(|id| server.role(id)) {
+                            Some(role) => !role.managed,
+                            None => true,
+                        };
+                        ui.add_enabled_ui(editable, |ui| {
+                            role_editor(ui, manager);
+                        });
+                        ui.horizontal(|ui| {
+                            let save_text = if manager.selected_role.is_some() { "Save" } else { "Create" };
+                            if ui.add_enabled(editable, egui::Button::new(save_text)).clicked() {
+                                let (name, color, permissions) = (manager.name.clone(), manager.color_value(), manager.permissions);
+                                job = Some(match manager.selected_role {
+                                    Some(role_id) => Job::EditRole(EditRole::new(
+                                        server_id, role_id, name, color, permissions, manager.hoist, manager.mentionable
+                                    )),
+                                    None => Job::CreateRole(CreateRole::new(
+                                        server_id, name, color, permissions, manager.hoist, manager.mentionable
+                                    )),
+                                });
+                            }
+                            // @everyone shares the server's id and can't be deleted
+                            let deletable = editable && manager.selected_role.is_some_and(|id| id != server_id);
+                            if deletable && ui.button("Delete role").clicked() {
+                                let role_id = manager.selected_role.unwrap();
+                                job = Some(Job::DeleteRole(DeleteRole::new(server_id, role_id)));
+                                // back to a blank "Create" form rather than the deleted role's settings
+                                *manager = RoleManager::new();
+                                manager.open = true;
+                            }
+                        });
+                    });
+                });
+            });
+        if let Some(job) = job {
+            self.append_job(job);
+        }
+        if !open {
+            self.role_manager.open = false;
+        }
+    }
+}
+
+fn role_editor(ui: &mut egui::Ui, manager: &mut RoleManager) {
+    egui::Grid::new("role_settings").num_columns(2).show(ui, |ui| {
+        ui.label("Name");
+        ui.text_edit_singleline(&mut manager.name);
+        ui.end_row();
+        ui.label("Color");
+        ui.color_edit_button_srgb(&mut manager.color);
+        ui.end_row();
+        ui.label("Display separately");
+        ui.checkbox(&mut manager.hoist, "");
+        ui.end_row();
+        ui.label("Mentionable");
+        ui.checkbox(&mut manager.mentionable, "");
+        ui.end_row();
+    });
+    ui.separator();
+    egui::ScrollArea::vertical()
+        .id_source("role_permissions")
+        .max_height(250.0)
+        .show(ui, |ui| {
+            egui::Grid::new("role_permission_grid").num_columns(2).show(ui, |ui| {
+                for row in PERMISSION_NAMES.chunks(2) {
+                    for (name, permission) in row {
+                        let mut enabled = manager.permissions.contains(*permission);
+                        if ui.checkbox(&mut enabled, *name).changed() {
+                            manager.permissions.set(*permission, enabled);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
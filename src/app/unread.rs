@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::fs;
+use egui::{Color32, Margin, RichText, Sense, Ui, Vec2};
+use serde::{Deserialize, Serialize};
+use crate::app::DiscordApp;
+use crate::discord::jobs::{GetActivity, Job};
+use crate::discord::shared_cache::ChannelActivity;
+
+pub const READ_STATE_PATH: &str = "res/read_state.json";
+
+// The last message read in every channel, kept between sessions
+#[derive(Default, Serialize, Deserialize)]
+pub struct ReadState {
+    last_read: HashMap<u64, u64>, // by channel id
+}
+
+impl ReadState {
+    // A missing or broken file starts with nothing read
+    pub fn load(path: &str) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string(self).expect("Read state is serializable");
+        fs::write(path, json)
+    }
+
+    pub fn last_read(&self) -> &HashMap<u64, u64> {
+        &self.last_read
+    }
+
+    // Returns true if it moved forward
+    pub fn mark_read(&mut self, channel_id: u64, message_id: u64) -> bool {
+        let last_read = self.last_read.entry(channel_id).or_insert(0);
+        if message_id <= *last_read {
+            return false;
+        }
+        *last_read = message_id;
+        true
+    }
+
+    pub fn channel(&self, channel_id: u64, activity: &ChannelActivity) -> Unread {
+        let last_read = self.last_read.get(&channel_id).copied().unwrap_or(0);
+        Unread {
+            unread: activity.last_message_id > last_read,
+            mentions: activity.mention_ids.iter().filter(|id| **id > last_read).count(),
+        }
+    }
+
+    pub fn server(&self, server_id: u64, activity: &HashMap<u64, ChannelActivity>) -> Unread {
+        let mut total = Unread::default();
+        for (channel_id, channel) in activity.iter().filter(|(_, channel)| channel.server_id == server_id) {
+            let unread = self.channel(*channel_id, channel);
+            total.unread |= unread.unread;
+            total.mentions += unread.mentions;
+        }
+        total
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Unread {
+    pub unread: bool,
+    pub mentions: usize,
+}
+
+impl DiscordApp {
+    pub fn mark_read(&mut self, channel_id: u64, message_id: u64) {
+        if self.read_state.mark_read(channel_id, message_id) {
+            self.save_read_state();
+        }
+    }
+
+    // Every channel of the server up to its newest message
+    pub fn mark_server_read(&mut self, server_id: u64) {
+        let activity = self.cache.activity.get();
+        let mut changed = false;
+        for (channel_id, channel) in activity.iter().filter(|(_, channel)| channel.server_id == server_id) {
+            changed |= self.read_state.mark_read(*channel_id, channel.last_message_id);
+        }
+        if changed {
+            self.save_read_state();
+        }
+    }
+
+    // Once per server, later changes come from fetching its channels and messages
+    pub fn request_activity(&mut self) {
+        let servers = self.cache.servers.get();
+        for server in servers.iter() {
+            if self.activity_requested.insert(server.id) {
+                let job = GetActivity::new(server.id, self.read_state.last_read().clone());
+                self.append_job(Job::GetActivity(job));
+            }
+        }
+    }
+
+    fn save_read_state(&self) {
+        if let Err(err) = self.read_state.save(READ_STATE_PATH) {
+            println!("Couldn't save read state: {}", err);
+        }
+    }
+}
+
+// A count for mentions, a dot for anything else unread
+pub fn badge(ui: &mut Ui, unread: Unread) {
+    if unread.mentions > 0 {
+        let count = RichText::new(unread.mentions.to_string()).small().strong().color(Color32::WHITE);
+        egui::Frame::none()
+            .fill(ui.visuals().error_fg_color)
+            .rounding(8.0)
+            .inner_margin(Margin::symmetric(5.0, 0.0))
+            .show(ui, |ui| ui.label(count))
+            .response
+            .on_hover_text(format!("{} unread mentions", unread.mentions));
+    } else if unread.unread {
+        let (rect, _) = ui.allocate_exact_size(Vec2::splat(8.0), Sense::hover());
+        ui.painter().circle_filled(rect.center(), 3.0, ui.visuals().strong_text_color());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(last_message_id: u64, mention_ids: Vec<u64>) -> ChannelActivity {
+        ChannelActivity { server_id: 1, last_message_id, mention_ids }
+    }
+
+    #[test]
+    fn mentions_before_the_last_read_are_not_counted() {
+        let mut state = ReadState::default();
+        let channel = activity(30, vec![10, 20, 30]);
+        assert_eq!(state.channel(5, &channel).mentions, 3);
+        assert!(state.mark_read(5, 20));
+        let unread = state.channel(5, &channel);
+        assert!(unread.unread);
+        assert_eq!(unread.mentions, 1);
+        assert!(!state.mark_read(5, 10));
+        assert!(state.mark_read(5, 30));
+        assert!(!state.channel(5, &channel).unread);
+    }
+
+    #[test]
+    fn server_totals_only_count_its_channels() {
+        let mut state = ReadState::default();
+        let mut channels = HashMap::new();
+        channels.insert(5, activity(30, vec![30]));
+        channels.insert(6, activity(40, vec![35, 40]));
+        channels.insert(7, ChannelActivity { server_id: 2, last_message_id: 50, mention_ids: vec![50] });
+        state.mark_read(5, 30);
+        let unread = state.server(1, &channels);
+        assert!(unread.unread);
+        assert_eq!(unread.mentions, 2);
+    }
+}
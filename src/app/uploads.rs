@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+use egui::{Align2, Color32, Context, FontId, Id, LayerId, Order, RichText, Stroke, Ui, Vec2};
+use crate::app::DiscordApp;
+use crate::discord::guild::BASE_UPLOAD_LIMIT;
+use crate::discord::jobs::{AttachFiles, FileUpload, Job, SendFile};
+use crate::discord::shared_cache::UploadStatus;
+use crate::discord::util;
+
+const CHIP_WIDTH: f32 = 180.0;
+const THUMBNAIL_SIZE: f32 = 32.0;
+
+fn preview_uri(file: &FileUpload) -> String {
+    format!("bytes://upload/{}/{}", file.id, file.filename)
+}
+
+impl DiscordApp {
+    pub fn upload_limit(&self) -> u64 {
+        let servers = self.cache.servers.get();
+        servers.iter()
+            .find(|server| server.id == self.selected_server_id)
+            .map_or(BASE_UPLOAD_LIMIT, |server| server.upload_limit())
+    }
+
+    // Why the queued files can't be sent, the limit applies to each file and to the whole request
+    pub fn upload_blocked(&self) -> Option<String> {
+        let limit = self.upload_limit();
+        let files = self.shared_cache.pending_files.load();
+        let too_large = files.iter().filter(|file| file.bytes.len() as u64 > limit).count();
+        if too_large > 0 {
+            return Some(format!("{} file(s) over the {} limit, remove them to send", too_large, util::format_size(limit)));
+        }
+        let total: u64 = files.iter().map(|file| file.bytes.len() as u64).sum();
+        if total > limit {
+            return Some(format!("{} in total, over the {} limit per message", util::format_size(total), util::format_size(limit)));
+        }
+        None
+    }
+
+    pub fn files_fit(&self) -> bool {
+        self.upload_blocked().is_none()
+    }
+
+    // Files dropped onto the window and images pasted from the clipboard end up in the composer
+    pub fn receive_files(&mut self, ctx: &Context) {
+        let dropped: Vec<PathBuf> = ctx.input(|i| {
+            i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect()
+        });
+        if !dropped.is_empty() {
+            self.append_job(Job::AttachFiles(AttachFiles::new(dropped)));
+        }
+        if util::pasted_image(ctx) {
+            self.append_job(Job::PasteImage);
+        }
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop")));
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, Color32::from_black_alpha(160));
+            painter.text(screen.center(), Align2::CENTER_CENTER, "Drop to attach", FontId::proportional(24.0), Color32::WHITE);
+        }
+    }
+
+    // Queued files as removable chips above the message field, with the upload status
+    pub fn pending_files_bar(&mut self, ui: &mut Ui) {
+        let limit = self.upload_limit();
+        let blocked = self.upload_blocked();
+        match &*self.shared_cache.upload.load() {
+            UploadStatus::Idle => {}
+            UploadStatus::Uploading(files, sent, total) => {
+                let text = format!("Uploading {} file(s), {} / {}", files, util::format_size(*sent), util::format_size(*total));
+                let fraction = if *total == 0 { 0.0 } else { *sent as f32 / *total as f32 };
+                ui.add(egui::ProgressBar::new(fraction).text(text));
+            }
+            UploadStatus::Failed(err) => {
+                ui.colored_label(ui.visuals().error_fg_color, format!("Upload failed: {}", err));
+            }
+        }
+        // drawn from a snapshot, changes are written back by id
+        let files = self.shared_cache.pending_files.load();
+        if files.is_empty() {
+            return;
+        }
+        let mut removed = None;
+        let mut edited = None;
+        ui.horizontal_wrapped(|ui| {
+            for (index, file) in files.iter().enumerate() {
+                let too_large = file.bytes.len() as u64 > limit;
+                let stroke = if too_large {
+                    Stroke::new(1.0, ui.visuals().error_fg_color)
+                } else {
+                    ui.visuals().widgets.noninteractive.bg_stroke
+                };
+                egui::Frame::none()
+                    .fill(ui.visuals().faint_bg_color)
+                    .stroke(stroke)
+                    .rounding(4.0)
+                    .inner_margin(6.0)
+                    .show(ui, |ui| {
+                        ui.set_width(CHIP_WIDTH);
+                        ui.push_id(("pending_file", index), |ui| {
+                            ui.horizontal(|ui| {
+                                if util::is_image_file(&file.filename) {
+                                    let uri = preview_uri(file);
+                                    // registered once, the loader keeps it until the file is gone
+                                    if ui.ctx().try_load_bytes(&uri).is_err() {
+                                        ui.ctx().include_bytes(uri.clone(), file.bytes.clone());
+                                    }
+                                    ui.add(egui::Image::new(uri)
+                                        .fit_to_exact_size(Vec2::splat(THUMBNAIL_SIZE))
+                                        .rounding(3.0));
+                                }
+                                ui.label(RichText::new(&file.filename).strong())
+                                    .on_hover_text(&file.filename);
+                                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                    if ui.small_button("✖").clicked() {
+                                        removed = Some(file);
+                                    }
+                                });
+                            });
+                            let size = util::format_size(file.bytes.len() as u64);
+                            if too_large {
+                                let text = format!("{}, over the {} limit", size, util::format_size(limit));
+                                ui.colored_label(ui.visuals().error_fg_color, RichText::new(text).small());
+                            } else {
+                                ui.small(size);
+                            }
+                            let mut spoiler = file.spoiler;
+                            let mut description = file.description.clone();
+                            ui.checkbox(&mut spoiler, "Spoiler");
+                            ui.add(egui::TextEdit::singleline(&mut description)
+                                .hint_text("Description (alt text)"));
+                            if spoiler != file.spoiler || description != file.description {
+                                edited = Some((file.id, spoiler, description));
+                            }
+                        });
+                    });
+            }
+        });
+        if let Some(err) = blocked {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+        if let Some((id, spoiler, description)) = edited {
+            self.shared_cache.pending_files.update(|files| {
+                if let Some(file) = files.iter_mut().find(|file| file.id == id) {
+                    file.spoiler = spoiler;
+                    file.description = description;
+                }
+            });
+        }
+        if let Some(file) = removed {
+            self.shared_cache.pending_files.update(|files| files.retain(|queued| queued.id != file.id));
+            ui.ctx().forget_image(&preview_uri(file));
+            self.shared_cache.upload.update(UploadStatus::queue_changed);
+        }
+    }
+
+    // The message text goes along with the files
+    pub fn send_pending_files(&mut self, ctx: &Context) {
+        let files = self.shared_cache.pending_files.update(std::mem::take);
+        for file in &files {
+            ctx.forget_image(&preview_uri(file));
+        }
+        let content = std::mem::take(&mut self.input_text);
+        let reply_id = (self.reply_message_id != 0).then_some(self.reply_message_id);
+        self.reply_message_id = 0;
+        let job = SendFile::new(self.selected_channel_id, content, files, reply_id);
+        self.append_job(Job::SendFile(job));
+    }
+}
@@ -3,7 +3,7 @@ use std::thread;
 use crate::app::DiscordApp;
 use crate::discord::event_thread::EventController;
 use crate::discord::shared_cache::{ArcMutex, Queue, SharedCache};
-use crate::config::Config;
+use crate::config::{Config, CONFIG_PATH};
 
 use eframe::AppCreator;
 use egui::Vec2;
@@ -16,7 +16,7 @@ impl eframe::App for DiscordApp {
 
 pub fn run_app() {
     println!("Running!");
-    let config = Config::read_config("res/config.json");
+    let config = Config::read_config(CONFIG_PATH);
 
     let options = eframe::NativeOptions {
         min_window_size: Some(Vec2::new(30.0, 30.0)),
@@ -26,14 +26,16 @@ pub fn run_app() {
 
     let shared_cache = Arc::new(SharedCache::new()); // shared in two places
     let job_queue = ArcMutex::new(Queue::new()); // shared in two places
-    let mut event_controller = EventController::new(
-        shared_cache.clone(),
-        job_queue.clone(),
-        config.token.clone());
-    thread::spawn(move || {
-        event_controller.idle();
-    });
     let app_creator: AppCreator = Box::new(|cc| {
+        // started here, it needs the context to wake the UI
+        let mut event_controller = EventController::new(
+            shared_cache.clone(),
+            job_queue.clone(),
+            config.token.clone(),
+            cc.egui_ctx.clone());
+        thread::spawn(move || {
+            event_controller.idle();
+        });
         egui_extras::install_image_loaders(&cc.egui_ctx);
         let mut window = DiscordApp::new(&cc.egui_ctx, shared_cache, job_queue, config);
         window.setup();
@@ -1,8 +1,9 @@
 #![warn(clippy::all)]
 #![allow(clippy::single_match)]
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 // Re-export dependencies.
 pub use egui;
 pub use gl;
@@ -306,6 +307,13 @@ pub fn input_to_egui(
             state.input.events.push(Event::Text(text));
         }
 
+        DropFile { filename, .. } => {
+            state.input.dropped_files.push(DroppedFile {
+                path: Some(std::path::PathBuf::from(filename)),
+                ..Default::default()
+            });
+        }
+
         MouseWheel { x, y, .. } => {
             let delta = vec2(x as f32 * 8.0, y as f32 * 8.0);
             let sdl = window.subsystem().sdl();
@@ -425,7 +433,7 @@ pub fn run_app() {
     use sdl2::video::SwapInterval;
 
     use std::time::Instant;
-    use crate::config::Config;
+    use crate::config::{Config, CONFIG_PATH};
     use crate::app::DiscordApp;
 
     const SCREEN_WIDTH: u32 = 800;
@@ -454,18 +462,37 @@ pub fn run_app() {
     let egui_context = egui::Context::default();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
+    // repaints requested from other threads wake up the event loop with an empty user event
+    let event_subsystem = sdl_context.event().unwrap();
+    let repaint_event = unsafe { event_subsystem.register_event() }.unwrap();
+    let event_sender = Mutex::new(event_subsystem.event_sender());
+    egui_context.set_request_repaint_callback(move |_| {
+        let wake_up = Event::User {
+            timestamp: 0,
+            window_id: 0,
+            type_: repaint_event,
+            code: 0,
+            data1: std::ptr::null_mut(),
+            data2: std::ptr::null_mut(),
+        };
+        if let Ok(sender) = event_sender.lock() {
+            let _ = sender.push_event(wake_up);
+        }
+    });
+
     let enable_vsync = false;
     let quit = false;
 
     let start_time = Instant::now();
 
-    let config = Config::read_config("res/config.json");
+    let config = Config::read_config(CONFIG_PATH);
     let shared_cache = Arc::new(SharedCache::new()); // shared in two places
     let job_queue = ArcMutex::new(Queue::new()); // shared in two places
     let mut event_controller = EventController::new(
         shared_cache.clone(),
         job_queue.clone(),
-        config.token.clone());
+        config.token.clone(),
+        egui_context.clone());
     thread::spawn(move || {
         event_controller.idle();
     });
@@ -520,24 +547,22 @@ pub fn run_app() {
         painter.paint_jobs(None, textures_delta, paint_jobs);
         window.gl_swap_window();
 
-        if !repaint_after.is_zero() {
-            if let Some(event) = event_pump.wait_event_timeout(5) {
-                match event {
-                    Event::Quit { .. } => break 'running,
-                    _ => {
-                        // Process input event
-                        egui_state.process_input(&window, event, &mut painter);
-                    }
-                }
-            }
+        // sleeps until there's input, a repaint request or egui wants the next frame
+        let first_event = if repaint_after.is_zero() {
+            event_pump.poll_event()
+        } else if repaint_after == Duration::MAX {
+            Some(event_pump.wait_event())
         } else {
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. } => break 'running,
-                    _ => {
-                        // Process input event
-                        egui_state.process_input(&window, event, &mut painter);
-                    }
+            let timeout = repaint_after.as_millis().min(u32::MAX as u128) as u32;
+            event_pump.wait_event_timeout(timeout)
+        };
+        let events: Vec<Event> = first_event.into_iter().chain(event_pump.poll_iter()).collect();
+        for event in events {
+            match event {
+                Event::Quit { .. } => break 'running,
+                _ => {
+                    // Process input event
+                    egui_state.process_input(&window, event, &mut painter);
                 }
             }
         }
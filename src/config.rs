@@ -1,12 +1,32 @@
 use serde::Deserialize;
 use serde::Serialize;
 use std::fs;
+use crate::media_policy::{MediaPolicy, RevealMode};
+use crate::time_format::TimeSettings;
+
+pub const CONFIG_PATH: &str = "res/config.json";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub token: String,
     pub enable_cache: bool,
     pub browser_name: String,
+    #[serde(default)]
+    pub download_dir: String, // empty for the system downloads folder
+    #[serde(default = "default_image_cache_mb")]
+    pub image_cache_mb: u64,
+    #[serde(default)]
+    pub media: MediaPolicy,
+    #[serde(default)]
+    pub trusted_link_domains: Vec<String>, // masked links to these open without a warning
+    #[serde(default)]
+    pub spoilers: RevealMode, // also covers all media in NSFW channels
+    #[serde(default)]
+    pub time: TimeSettings,
+}
+
+fn default_image_cache_mb() -> u64 {
+    256
 }
 
 impl Config {
@@ -16,4 +36,9 @@ impl Config {
         let config: Config = serde_json::from_str(a_str).expect("Erroneous config file");
         config
     }
+
+    pub fn save_config(&self, path: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("Config is serializable");
+        fs::write(path, json)
+    }
 }
@@ -3,4 +3,6 @@ pub mod twilight_client;
 pub mod jobs;
 pub mod event_thread;
 pub mod shared_cache;
-pub mod util;
\ No newline at end of file
+pub mod util;
+pub mod download;
+pub mod upload;
\ No newline at end of file
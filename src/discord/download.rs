@@ -0,0 +1,230 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::discord::shared_cache::ArcMutex;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone)]
+pub enum DownloadState {
+    Running,
+    Done(u32), // crc32 of the saved file
+    Failed(String),
+}
+
+#[derive(Clone)]
+pub struct DownloadProgress {
+    pub filename: String,
+    pub path: PathBuf,
+    pub received: u64,
+    pub size: u64, // 0 if unknown
+    pub state: DownloadState,
+}
+
+impl DownloadProgress {
+    pub fn new(filename: String, size: u64) -> Self {
+        Self{ filename, path: PathBuf::new(), received: 0, size, state: DownloadState::Running }
+    }
+}
+
+// An empty setting means the system downloads folder
+pub fn download_directory(configured: &str) -> PathBuf {
+    if !configured.trim().is_empty() {
+        return PathBuf::from(configured.trim());
+    }
+    dirs_next::download_dir().unwrap_or(PathBuf::from("downloads"))
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.part", path.display()))
+}
+
+const RESERVED_NAMES: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+
+// Names come from the server, only the last component is kept and made safe on every platform
+pub fn safe_filename(filename: &str, attachment_id: u64) -> String {
+    let name = Path::new(filename).file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let name: String = name.chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect();
+    // Windows drops trailing dots and spaces, which could turn the name into another one
+    let name = name.trim_end_matches(['.', ' ']).trim_start();
+    if name.is_empty() {
+        return format!("attachment-{}", attachment_id);
+    }
+    let stem = name.split('.').next().unwrap_or_default().to_uppercase();
+    let device = RESERVED_NAMES.contains(&stem.as_str())
+        || ((stem.starts_with("COM") || stem.starts_with("LPT")) && stem.len() == 4 && stem.as_bytes()[3].is_ascii_digit());
+    if device {
+        return format!("_{}", name);
+    }
+    name.to_string()
+}
+
+// "name.ext" for 0, then "name (1).ext" and so on
+fn numbered(directory: &Path, filename: &str, n: usize) -> PathBuf {
+    if n == 0 {
+        return directory.join(filename);
+    }
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (filename, String::new()),
+    };
+    directory.join(format!("{} ({}){}", stem, n, extension))
+}
+
+// Existing files are never overwritten, "name (1).ext" is tried next and so on.
+// The temporary file is created exclusively, so concurrent downloads of the same name can't share it.
+fn claim_part_file(directory: &Path, filename: &str) -> io::Result<(PathBuf, File)> {
+    for n in 0.. {
+        let path = numbered(directory, filename, n);
+        if path.exists() {
+            continue;
+        }
+        match OpenOptions::new().write(true).create_new(true).open(part_path(&path)) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+// Fails with AlreadyExists instead of replacing a file that appeared in the meantime
+fn move_no_clobber(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => Err(err),
+        // file systems without hard links
+        Err(_) if to.exists() => Err(ErrorKind::AlreadyExists.into()),
+        Err(_) => fs::rename(from, to),
+    }
+}
+
+// The next free name if the claimed one was taken while downloading
+fn finish(part_path: &Path, directory: &Path, filename: &str, claimed: &Path) -> io::Result<PathBuf> {
+    let mut path = claimed.to_path_buf();
+    for n in 1.. {
+        match move_no_clobber(part_path, &path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => path = numbered(directory, filename, n),
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+// Blocking, meant to be run off the async workers.
+// The file is written under a temporary name and only moved in place once its size checks out.
+pub fn download_file(url: &str, directory: &Path, progress: &ArcMutex<DownloadProgress>) -> Result<(), String> {
+    fs::create_dir_all(directory).map_err(|err| format!("Couldn't create {}: {}", directory.display(), err))?;
+    let response = ureq::get(url).call().map_err(|err| err.to_string())?;
+    // the size Discord reported, or what the server announced if that's unknown
+    let mut expected = progress.guard().size;
+    if expected == 0 {
+        expected = response.header("Content-Length").and_then(|length| length.parse().ok()).unwrap_or(0);
+    }
+    save(&mut response.into_reader(), directory, expected, progress)
+}
+
+fn save(reader: &mut impl Read, directory: &Path, expected: u64, progress: &ArcMutex<DownloadProgress>) -> Result<(), String> {
+    let filename = progress.guard().filename.clone();
+    let (path, mut file) = claim_part_file(directory, &filename).map_err(|err| err.to_string())?;
+    let part_path = part_path(&path);
+    progress.guard().path = path.clone();
+
+    let result = receive(reader, &mut file, progress)
+        .and_then(|(received, checksum)| check_size(expected, received).map(|()| checksum));
+    drop(file);
+    let checksum = match result {
+        Ok(checksum) => checksum,
+        Err(err) => {
+            let _ = fs::remove_file(&part_path);
+            return Err(err);
+        }
+    };
+    let path = finish(&part_path, directory, &filename, &path).map_err(|err| {
+        let _ = fs::remove_file(&part_path);
+        err.to_string()
+    })?;
+    let mut progress = progress.guard();
+    progress.path = path;
+    progress.state = DownloadState::Done(checksum);
+    Ok(())
+}
+
+// Returns the byte count and the crc32 of what was written
+fn receive(reader: &mut impl Read, file: &mut File, progress: &ArcMutex<DownloadProgress>) -> Result<(u64, u32), String> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut received = 0;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buffer).map_err(|err| err.to_string())?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read]).map_err(|err| err.to_string())?;
+        hasher.update(&buffer[..read]);
+        received += read as u64;
+        progress.guard().received = received;
+    }
+    file.flush().map_err(|err| err.to_string())?;
+    Ok((received, hasher.finalize()))
+}
+
+// Discord doesn't publish checksums, a cut off transfer shows in the size.
+// Without a known size there's nothing to compare against.
+fn check_size(expected: u64, received: u64) -> Result<(), String> {
+    if expected != 0 && expected != received {
+        return Err(format!("Expected {} bytes, received {}", expected, received));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("discord-bot-gui-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn filenames_stay_in_the_directory() {
+        assert_eq!(safe_filename("../../.bashrc", 1), ".bashrc");
+        assert_eq!(safe_filename("/etc/passwd", 1), "passwd");
+        assert_eq!(safe_filename("a<b>:c?.png", 1), "a_b__c_.png");
+        assert_eq!(safe_filename("..", 7), "attachment-7");
+        assert_eq!(safe_filename("", 7), "attachment-7");
+        assert_eq!(safe_filename("con.txt", 1), "_con.txt");
+    }
+
+    #[test]
+    fn truncated_download_is_discarded() {
+        let directory = temp_directory("truncated");
+        let progress = ArcMutex::new(DownloadProgress::new("cat.png".into(), 10));
+        let result = save(&mut Cursor::new(vec![1, 2, 3, 4]), &directory, 10, &progress);
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn existing_files_are_kept() {
+        let directory = temp_directory("existing");
+        fs::write(directory.join("cat.png"), b"old").unwrap();
+        let progress = ArcMutex::new(DownloadProgress::new("cat.png".into(), 3));
+        save(&mut Cursor::new(b"new".to_vec()), &directory, 3, &progress).unwrap();
+        assert_eq!(fs::read(directory.join("cat.png")).unwrap(), b"old");
+        assert_eq!(fs::read(directory.join("cat (1).png")).unwrap(), b"new");
+        // a second claim of the same name while one is in progress gets its own file
+        let (first, _file) = claim_part_file(&directory, "dog.png").unwrap();
+        let (second, _file) = claim_part_file(&directory, "dog.png").unwrap();
+        assert_ne!(first, second);
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -1,26 +1,46 @@
+use std::collections::HashMap;
+use std::ops::Deref;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::{runtime, time};
+use tokio::sync::{OnceCell, Semaphore};
 use twilight_http::Client;
+use twilight_model::id::Id;
 use std::fs::File;
+use std::path::PathBuf;
 use std::io::Read;
 use std::io::BufReader;
 use std::thread;
 use native_dialog::FileDialog;
-use crate::discord::jobs::{CreateChannel, DeleteChannel, DeleteMessage, EditMessage, GetChannels, GetGuildPreview, GetMembers, GetMessages, Job, SendFile, SendMessage};
-use crate::discord::shared_cache::{ArcMutex, Queue, SharedCache};
-use crate::discord::twilight_client;
+use twilight_model::channel::{Channel, ChannelType, Message};
+use twilight_model::channel::permission_overwrite::{PermissionOverwrite, PermissionOverwriteType as OverwriteKind};
+use twilight_model::http::permission_overwrite::PermissionOverwriteType;
+use crate::discord::jobs::{CreateChannel, DeleteChannel, DeleteMessage, DeletePermissionOverwrite, EditChannel, EditMessage, EditPermissionOverwrite, GetChannels, GetGuildPreview, GetMembers, GetMessages, GetUser, Job, ReorderChannels, SearchMembers, SendFile, SendMessage};
+use crate::discord::jobs::{AddMemberRole, CreateRole, DeleteRole, EditRole, FileUpload, GetGuild, GetRoles, RemoveMemberRole};
+use crate::discord::jobs::{BanMember, GetAuditLog, GetBans, KickMember, TimeoutMember, UnbanMember};
+use crate::discord::jobs::{AttachFiles, Download, GetActivity};
+use crate::discord::{download, upload};
+use crate::discord::download::{DownloadProgress, DownloadState};
+use crate::discord::shared_cache::{ArcMutex, ChannelActivity, Queue, SharedCache, UploadStatus};
+use crate::discord::{twilight_client, util};
+
+const MEMBER_PAGES_PER_UPDATE: usize = 10; // published while the rest is fetched
+const MENTION_SEARCH_LIMIT: u16 = 50; // unread messages searched per channel
+const MENTION_SEARCH_INTERVAL: Duration = Duration::from_millis(250); // between the channels searched
 
 pub struct EventController {
     pub idling: bool,
     tokio: runtime::Runtime,
     shared_data: Arc<SharedCache>,
     job_queue: ArcMutex<Queue<Job>>,
-    client: Arc<Client>
+    client: Arc<Client>,
+    ctx: egui::Context, // woken up whenever the cache changes
+    current_user_id: Arc<OnceCell<u64>>, // fetched once, when first needed
+    activity_fetches: Arc<Semaphore>, // one server's activity at a time
 }
 
 impl EventController{
-    pub fn new(shared_data: Arc<SharedCache>, job_queue: ArcMutex<Queue<Job>>, token: String) -> Self {
+    pub fn new(shared_data: Arc<SharedCache>, job_queue: ArcMutex<Queue<Job>>, token: String, ctx: egui::Context) -> Self {
         let tokio_runtime = runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
@@ -31,6 +51,9 @@ impl EventController{
             client: Arc::new(twilight_client::create_client(token)),
             idling: false,
             tokio: tokio_runtime,
+            ctx,
+            current_user_id: Arc::new(OnceCell::new()),
+            activity_fetches: Arc::new(Semaphore::new(1)),
         }
     }
     //should be launched on a separate thread
@@ -72,6 +95,12 @@ impl EventController{
             Job::GetMembers(member_fetch) => {
                 self.get_members(member_fetch)
             }
+            Job::SearchMembers(member_search) => {
+                self.search_members(member_search)
+            }
+            Job::GetUser(user_fetch) => {
+                self.get_user(user_fetch)
+            }
             Job::SendMessage(msg_send) => {
                 self.send_message(msg_send)
             }
@@ -81,6 +110,12 @@ impl EventController{
             Job::SelectFile => {
                 self.select_file()
             }
+            Job::AttachFiles(files_attach) => {
+                self.attach_files(files_attach)
+            }
+            Job::PasteImage => {
+                self.paste_image()
+            }
             Job::SendFile(file_send) => {
                 self.file_upload(file_send)
             }
@@ -93,176 +128,750 @@ impl EventController{
             Job::EditMessage(msg_edit) => {
                 self.edit_message(msg_edit)
             }
+            Job::EditChannel(channel_edit) => {
+                self.edit_channel(channel_edit)
+            }
+            Job::ReorderChannels(channel_reorder) => {
+                self.reorder_channels(channel_reorder)
+            }
+            Job::EditPermissionOverwrite(overwrite_edit) => {
+                self.edit_permission_overwrite(overwrite_edit)
+            }
+            Job::DeletePermissionOverwrite(overwrite_delete) => {
+                self.delete_permission_overwrite(overwrite_delete)
+            }
+            Job::GetGuild(guild_fetch) => {
+                self.get_guild(guild_fetch)
+            }
+            Job::GetRoles(roles_fetch) => {
+                self.get_roles(roles_fetch)
+            }
+            Job::CreateRole(role_create) => {
+                self.create_role(role_create)
+            }
+            Job::EditRole(role_edit) => {
+                self.edit_role(role_edit)
+            }
+            Job::DeleteRole(role_delete) => {
+                self.delete_role(role_delete)
+            }
+            Job::AddMemberRole(role_add) => {
+                self.add_member_role(role_add)
+            }
+            Job::RemoveMemberRole(role_remove) => {
+                self.remove_member_role(role_remove)
+            }
+            Job::KickMember(member_kick) => {
+                self.kick_member(member_kick)
+            }
+            Job::BanMember(member_ban) => {
+                self.ban_member(member_ban)
+            }
+            Job::TimeoutMember(member_timeout) => {
+                self.timeout_member(member_timeout)
+            }
+            Job::UnbanMember(member_unban) => {
+                self.unban_member(member_unban)
+            }
+            Job::GetBans(bans_fetch) => {
+                self.get_bans(bans_fetch)
+            }
+            Job::GetAuditLog(audit_log_fetch) => {
+                self.get_audit_log(audit_log_fetch)
+            }
+            Job::Download(download) => {
+                self.download(download)
+            }
+            Job::GetActivity(activity_fetch) => {
+                self.get_activity(activity_fetch)
+            }
             _ => {}
         }
     }
     fn get_servers(&mut self) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn( async move {
             let guilds = twilight_client::get_connected_servers(&client).await;
-            *cache.servers.guard() = guilds;
+            cache.servers.store(guilds);
         });
     }
     fn get_channels(&self, channel_fetch: GetChannels) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let channels = twilight_client::get_channels(&client, channel_fetch.server_id).await;
+            cache.categories.store(twilight_client::filter_categories(&channels));
             let split_channels = twilight_client::split_into_text_and_voice(channels);
-            *cache.channels.guard() = split_channels;
+            cache.activity.update(|activity| record_activity(activity, channel_fetch.server_id, &split_channels.0));
+            cache.channels.store(split_channels);
         });
     }
     fn get_messages(&self, msg_fetch: GetMessages) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let messages = twilight_client::get_messages(&client, msg_fetch.channel_id, msg_fetch.limit).await;
-            *cache.messages.guard() = messages;
+            if let Some(newest) = messages.first() {
+                cache.activity.update(|activity| record_message(activity, newest));
+            }
+            cache.messages.store(messages);
         });
     }
     fn get_members(&self, member_fetch: GetMembers) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            cache.members.store(vec![]);
+            let mut fetched = vec![];
+            let mut after = None;
+            for page_number in 1.. {
+                let page = twilight_client::get_members(&client, member_fetch.server_id, after, member_fetch.limit).await;
+                let last_page = page.len() < member_fetch.limit as usize;
+                after = page.last().map(|member| member.user.id.get());
+                fetched.extend(page);
+                // copying the whole list for every page would grow quadratically
+                if last_page || page_number % MEMBER_PAGES_PER_UPDATE == 0 {
+                    cache.members.store(fetched.clone());
+                    cache.repaint();
+                }
+                if last_page {
+                    break;
+                }
+            }
+        });
+    }
+    fn search_members(&self, member_search: SearchMembers) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
-            let members = twilight_client::get_members(&client, member_fetch.server_id, member_fetch.limit).await;
-            *cache.members.guard() = members;
+            let members = twilight_client::search_members(
+                &client, member_search.server_id, &member_search.query, member_search.limit
+            ).await;
+            cache.member_search.store(members);
+        });
+    }
+    fn get_user(&self, user_fetch: GetUser) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let user = twilight_client::get_user(&client, user_fetch.user_id).await;
+            cache.user_profile.store(user);
         });
     }
     fn edit_message(&self, edit: EditMessage) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let success = twilight_client::edit_message(&client, edit.channel_id, edit.message_id, edit.new_content.clone()).await;
             if !success {
                 return;
             }
-            let mut messages = cache.messages.guard();
-            let mut index = 0;
-            let mut found = false;
-            for (i, msg) in messages.iter().enumerate() {
-                if msg.id == edit.message_id {
-                    index = i;
-                    found = true;
-                    break;
+            cache.messages.update(|messages| {
+                let mut index = 0;
+                let mut found = false;
+                for (i, msg) in messages.iter().enumerate() {
+                    if msg.id == edit.message_id {
+                        index = i;
+                        found = true;
+                        break;
+                    }
                 }
-            }
-            if found {
-                messages[index].content = edit.new_content;
-            }
+                if found {
+                    messages[index].content = edit.new_content;
+                }
+            });
         });
     }
     fn send_message(&self, msg_send: SendMessage) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let message = twilight_client::send_message(
                 &client,
                 msg_send.channel_id,
                 msg_send.content.as_str(),
-                msg_send.reply_id
+                msg_send.reply_id,
+                &msg_send.embeds,
+                &msg_send.components
             ).await;
-            (*cache.messages.guard()).insert(0, message);
+            if let Some(message) = message {
+                cache.activity.update(|activity| record_message(activity, &message));
+                cache.messages.update(|messages| messages.insert(0, message));
+            }
         });
     }
     fn delete_message(&self, msg_delete: DeleteMessage) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let success = twilight_client::delete_message(&client, msg_delete.channel_id, msg_delete.message_id).await;
             if !success {
                 return;
             }
-            let mut messages = cache.messages.guard();
-            let mut index = 0;
-            let mut found = false;
-            for (i, msg) in messages.iter().enumerate() {
-                if msg.id == msg_delete.message_id {
-                    index = i;
-                    found = true;
-                    break;
+            cache.messages.update(|messages| {
+                let mut index = 0;
+                let mut found = false;
+                for (i, msg) in messages.iter().enumerate() {
+                    if msg.id == msg_delete.message_id {
+                        index = i;
+                        found = true;
+                        break;
+                    }
                 }
-            }
-            if found {
-                messages.remove(index);
-            }
+                if found {
+                    messages.remove(index);
+                }
+            });
         });
     }
     fn select_file(&self) {
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
-            let path = FileDialog::new()
+            let paths = FileDialog::new()
                 .set_location("~/Desktop")
-                .show_open_single_file()
+                .show_open_multiple_file()
                 .unwrap();
 
-            let path = match path {
-                Some(path) => path,
-                None => return,
+            let files: Vec<FileUpload> = paths.into_iter().filter_map(read_file).collect();
+            cache.pending_files.update(|pending| pending.extend(files));
+            cache.upload.update(UploadStatus::queue_changed);
+        });
+    }
+    fn attach_files(&self, files_attach: AttachFiles) {
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let files: Vec<FileUpload> = files_attach.paths.into_iter().filter_map(read_file).collect();
+            cache.pending_files.update(|pending| pending.extend(files));
+            cache.upload.update(UploadStatus::queue_changed);
+        });
+    }
+    // The clipboard holds raw pixels, they're attached as a PNG
+    fn paste_image(&self) {
+        let cache = self.cache_writer();
+        self.tokio.spawn_blocking(move || {
+            let Ok(mut clipboard) = arboard::Clipboard::new() else {
+                return;
+            };
+            let Ok(image) = clipboard.get_image() else {
+                return;
             };
-            let file_name = path.file_name().expect("No filename").to_str().unwrap().to_string();
-            let file = File::open(path).unwrap();
-            let mut reader = BufReader::new(file);
-            let mut bytes = Vec::new();
-            reader.read_to_end(&mut bytes).expect("Couldn't read all bytes?");
-            *cache.file_bytes.guard() = bytes;
-            *cache.file_name.guard() = file_name;
+            let Some(png) = util::encode_png(image.width as u32, image.height as u32, image.bytes.into_owned()) else {
+                return;
+            };
+            cache.pending_files.update(|pending| pending.push(FileUpload::new("image.png".into(), png)));
+            cache.upload.update(UploadStatus::queue_changed);
         });
     }
+    // Sent past twilight so the progress can be shown
     fn file_upload(&self, file_upload: SendFile) {
+        let token = self.client.token().unwrap_or_default().to_string();
+        let cache = self.cache_writer();
+        let file_count = file_upload.files.len();
+        cache.upload.store(UploadStatus::Uploading(file_count, 0, 0));
+        cache.repaint();
+        // the transfer itself is blocking
+        self.tokio.spawn_blocking(move || {
+            let result = upload::send_files(
+                &token,
+                file_upload.channel_id,
+                file_upload.content.as_str(),
+                &file_upload.files,
+                file_upload.reply_id,
+                |sent, total| {
+                    cache.upload.store(UploadStatus::Uploading(file_count, sent, total));
+                    cache.repaint();
+                },
+            );
+            match result {
+                Ok(msg_with_files) => {
+                    cache.activity.update(|activity| record_message(activity, &msg_with_files));
+                    cache.messages.update(|messages| messages.insert(0, msg_with_files));
+                    cache.upload.store(UploadStatus::Idle);
+                }
+                Err(err) => cache.upload.store(UploadStatus::Failed(err)),
+            }
+        });
+    }
+    fn download(&self, download: Download) {
+        let progress = ArcMutex::new(DownloadProgress::new(download.filename, download.size));
+        self.shared_data.downloads.guard().push(progress.clone());
+        self.ctx.request_repaint();
+        let ctx = self.ctx.clone();
+        // the transfer itself is blocking
+        self.tokio.spawn_blocking(move || {
+            let result = download::download_file(&download.url, &download.directory, &progress);
+            if let Err(err) = result {
+                progress.guard().state = DownloadState::Failed(err);
+            }
+            ctx.request_repaint();
+        });
+    }
+    // Channels read up to their newest message are skipped, the rest are searched for mentions.
+    // Servers take turns and the searches are spaced out, so the open channel isn't kept waiting
+    fn get_activity(&self, activity_fetch: GetActivity) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
+        let current_user_id = self.current_user_id.clone();
+        let activity_fetches = self.activity_fetches.clone();
         self.tokio.spawn(async move {
-            let msg_with_file = twilight_client::send_file(
-                &client,
-                file_upload.channel_id,
-                file_upload.filename,
-                file_upload.bytes
-            ).await;
-            let mut messages = cache.messages.guard();
-            messages.insert(0, msg_with_file);
+            let Ok(_turn) = activity_fetches.acquire().await else {
+                return;
+            };
+            let server_id = activity_fetch.server_id;
+            let channels = twilight_client::get_channels(&client, server_id).await;
+            let (text_channels, _) = twilight_client::split_into_text_and_voice(channels);
+            let user_id = current_user_id.get_or_try_init(|| async {
+                twilight_client::get_current_user(&client).await.map(|user| user.id.get()).ok_or(())
+            }).await;
+            let Ok(&user_id) = user_id else {
+                return;
+            };
+            let role_ids: Vec<u64> = match twilight_client::get_member(&client, server_id, user_id).await {
+                Some(member) => member.roles.iter().map(|role_id| role_id.get()).collect(),
+                None => vec![],
+            };
+            let mut fetched = HashMap::new();
+            record_activity(&mut fetched, server_id, &text_channels);
+            for (channel_id, channel_activity) in fetched.iter_mut() {
+                let last_read = activity_fetch.last_read.get(channel_id).copied().unwrap_or(0);
+                if channel_activity.last_message_id <= last_read {
+                    continue;
+                }
+                time::sleep(MENTION_SEARCH_INTERVAL).await;
+                let messages = if last_read == 0 {
+                    twilight_client::get_messages(&client, *channel_id, MENTION_SEARCH_LIMIT).await
+                } else {
+                    twilight_client::get_messages_after(&client, *channel_id, last_read, MENTION_SEARCH_LIMIT).await
+                };
+                channel_activity.mention_ids = messages.iter()
+                    .filter(|msg| util::mentions_user(msg, user_id, &role_ids))
+                    .map(|msg| msg.id.get())
+                    .collect();
+            }
+            cache.activity.update(|activity| merge_activity(activity, fetched));
         });
     }
+    fn cache_writer(&self) -> CacheWriter {
+        CacheWriter{
+            cache: self.shared_data.clone(),
+            ctx: self.ctx.clone(),
+        }
+    }
     pub fn take_job(&self) -> Job {
         let mut queue_guard = self.job_queue.guard();
         (*queue_guard).take()
     }
     fn create_channel(&self, channel_create: CreateChannel) {
         let client = self.client.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let channel_option = twilight_client::create_channel(
                 &client,
                 channel_create.server_id,
-                channel_create.name
+                channel_create.name,
+                channel_create.kind,
+                channel_create.parent_id,
             ).await;
+            if let Some(channel) = channel_option {
+                match channel.kind {
+                    ChannelType::GuildText => cache.channels.update(|channels| channels.0.push(channel)),
+                    ChannelType::GuildVoice => cache.channels.update(|channels| channels.1.push(channel)),
+                    ChannelType::GuildCategory => cache.categories.update(|categories| categories.push(channel)),
+                    _ => {}
+                }
+            }
         });
     }
     fn delete_channel(&self, delete_channel: DeleteChannel) {
         let client = self.client.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let channel_option = twilight_client::delete_channel(
                 &client,
                 delete_channel.channel_id,
             ).await;
+            if channel_option.is_none() {
+                return;
+            }
+            cache.channels.update(|channels| {
+                channels.0.retain(|channel| channel.id != delete_channel.channel_id);
+                channels.1.retain(|channel| channel.id != delete_channel.channel_id);
+            });
+            cache.categories.update(|categories| categories.retain(|channel| channel.id != delete_channel.channel_id));
+        });
+    }
+    fn edit_channel(&self, channel_edit: EditChannel) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            match twilight_client::edit_channel(&client, &channel_edit).await {
+                Ok(channel) => cache.channels.update(|channels| replace_channel(channels, channel)),
+                // the editor has closed by now, it's opened again with the error
+                Err(err) => cache.channel_error.store(Some((channel_edit.channel_id, err))),
+            }
+        });
+    }
+    fn reorder_channels(&self, channel_reorder: ReorderChannels) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::reorder_channels(
+                &client,
+                channel_reorder.server_id,
+                &channel_reorder.channels
+            ).await;
+            if success {
+                return;
+            }
+            // the UI reorders optimistically, restore the real order
+            let channels = twilight_client::get_channels(&client, channel_reorder.server_id).await;
+            cache.channels.store(twilight_client::split_into_text_and_voice(channels));
+        });
+    }
+    fn edit_permission_overwrite(&self, overwrite_edit: EditPermissionOverwrite) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::edit_permission_overwrite(&client, &overwrite_edit).await;
+            if !success {
+                return;
+            }
+            let kind = match overwrite_edit.kind {
+                PermissionOverwriteType::Member => OverwriteKind::Member,
+                _ => OverwriteKind::Role,
+            };
+            let overwrite = PermissionOverwrite {
+                allow: overwrite_edit.allow,
+                deny: overwrite_edit.deny,
+                id: Id::new(overwrite_edit.target_id),
+                kind,
+            };
+            cache.channels.update(|channels| {
+                if let Some(channel) = find_channel_mut(channels, overwrite_edit.channel_id) {
+                    let overwrites = channel.permission_overwrites.get_or_insert_with(Vec::new);
+                    overwrites.retain(|existing| existing.id != overwrite.id);
+                    overwrites.push(overwrite);
+                }
+            });
+        });
+    }
+    fn delete_permission_overwrite(&self, overwrite_delete: DeletePermissionOverwrite) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::delete_permission_overwrite(
+                &client,
+                overwrite_delete.channel_id,
+                overwrite_delete.target_id,
+                overwrite_delete.kind
+            ).await;
+            if !success {
+                return;
+            }
+            cache.channels.update(|channels| {
+                let channel = find_channel_mut(channels, overwrite_delete.channel_id);
+                if let Some(overwrites) = channel.and_then(|channel| channel.permission_overwrites.as_mut()) {
+                    overwrites.retain(|existing| existing.id != overwrite_delete.target_id);
+                }
+            });
         });
     }
     fn guild_preview(&self, guild_preview: GetGuildPreview) {
         let client = self.client.clone();
-        let cache = self.shared_data.clone();
+        let cache = self.cache_writer();
         self.tokio.spawn(async move {
             let preview = twilight_client::get_guild_preview(
                 &client,
                 guild_preview.server_id,
             ).await;
-            let mut servers = cache.servers.guard();
-            for server in servers.iter_mut() {
-                if server.id == guild_preview.server_id {
-                    server.preview = Some(preview);
-                    break;
+            cache.servers.update(|servers| {
+                for server in servers.iter_mut() {
+                    if server.id == guild_preview.server_id {
+                        server.preview = Some(preview);
+                        break;
+                    }
+                }
+            });
+        });
+    }
+    fn get_guild(&self, guild_fetch: GetGuild) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let tier = twilight_client::get_premium_tier(&client, guild_fetch.server_id).await;
+            cache.servers.update(|servers| {
+                if let Some(server) = servers.iter_mut().find(|server| server.id == guild_fetch.server_id) {
+                    server.premium_tier = tier;
+                }
+            });
+        });
+    }
+    fn get_roles(&self, roles_fetch: GetRoles) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let roles = twilight_client::get_roles(&client, roles_fetch.server_id).await;
+            cache.servers.update(|servers| {
+                if let Some(server) = servers.iter_mut().find(|server| server.id == roles_fetch.server_id) {
+                    server.set_roles(roles);
+                }
+            });
+        });
+    }
+    fn create_role(&self, role_create: CreateRole) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let Some(role) = twilight_client::create_role(&client, &role_create).await else {
+                return;
+            };
+            cache.servers.update(|servers| {
+                if let Some(server) = servers.iter_mut().find(|server| server.id == role_create.server_id) {
+                    let mut roles = std::mem::take(&mut server.roles);
+                    roles.push(role);
+                    server.set_roles(roles);
+                }
+            });
+        });
+    }
+    fn edit_role(&self, role_edit: EditRole) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let Some(role) = twilight_client::edit_role(&client, &role_edit).await else {
+                return;
+            };
+            cache.servers.update(|servers| {
+                if let Some(server) = servers.iter_mut().find(|server| server.id == role_edit.server_id) {
+                    let mut roles = std::mem::take(&mut server.roles);
+                    roles.retain(|existing| existing.id != role.id);
+                    roles.push(role);
+                    server.set_roles(roles);
+                }
+            });
+        });
+    }
+    fn delete_role(&self, role_delete: DeleteRole) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::delete_role(&client, role_delete.server_id, role_delete.role_id).await;
+            if !success {
+                return;
+            }
+            cache.servers.update(|servers| {
+                if let Some(server) = servers.iter_mut().find(|server| server.id == role_delete.server_id) {
+                    server.roles.retain(|role| role.id != role_delete.role_id);
+                }
+            });
+            cache.members.update(|members| {
+                for member in members.iter_mut() {
+                    member.roles.retain(|role_id| *role_id != role_delete.role_id);
+                }
+            });
+        });
+    }
+    fn add_member_role(&self, role_add: AddMemberRole) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::add_member_role(
+                &client,
+                role_add.server_id,
+                role_add.user_id,
+                role_add.role_id
+            ).await;
+            if !success {
+                return;
+            }
+            cache.members.update(|members| {
+                if let Some(member) = members.iter_mut().find(|member| member.user.id == role_add.user_id) {
+                    if !member.roles.iter().any(|role_id| *role_id == role_add.role_id) {
+                        member.roles.push(Id::new(role_add.role_id));
+                    }
+                }
+            });
+        });
+    }
+    fn remove_member_role(&self, role_remove: RemoveMemberRole) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::remove_member_role(
+                &client,
+                role_remove.server_id,
+                role_remove.user_id,
+                role_remove.role_id
+            ).await;
+            if !success {
+                return;
+            }
+            cache.members.update(|members| {
+                if let Some(member) = members.iter_mut().find(|member| member.user.id == role_remove.user_id) {
+                    member.roles.retain(|role_id| *role_id != role_remove.role_id);
+                }
+            });
+        });
+    }
+    fn kick_member(&self, member_kick: KickMember) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::kick_member(
+                &client,
+                member_kick.server_id,
+                member_kick.user_id,
+                &member_kick.reason
+            ).await;
+            if success {
+                cache.members.update(|members| members.retain(|member| member.user.id != member_kick.user_id));
+            }
+        });
+    }
+    fn ban_member(&self, member_ban: BanMember) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::ban_member(
+                &client,
+                member_ban.server_id,
+                member_ban.user_id,
+                member_ban.delete_message_days,
+                &member_ban.reason
+            ).await;
+            if success {
+                cache.members.update(|members| members.retain(|member| member.user.id != member_ban.user_id));
+            }
+        });
+    }
+    fn timeout_member(&self, member_timeout: TimeoutMember) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let member_option = twilight_client::timeout_member(
+                &client,
+                member_timeout.server_id,
+                member_timeout.user_id,
+                member_timeout.duration_secs,
+                &member_timeout.reason
+            ).await;
+            let Some(updated) = member_option else {
+                return;
+            };
+            cache.members.update(|members| {
+                if let Some(member) = members.iter_mut().find(|member| member.user.id == updated.user.id) {
+                    *member = updated;
                 }
+            });
+        });
+    }
+    fn unban_member(&self, member_unban: UnbanMember) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let success = twilight_client::unban_member(
+                &client,
+                member_unban.server_id,
+                member_unban.user_id,
+                &member_unban.reason
+            ).await;
+            if success {
+                cache.bans.update(|(server_id, bans)| if *server_id == member_unban.server_id {
+                    bans.retain(|ban| ban.user.id != member_unban.user_id);
+                });
             }
         });
     }
+    fn get_bans(&self, bans_fetch: GetBans) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let bans = twilight_client::get_bans(&client, bans_fetch.server_id).await;
+            cache.bans.store((bans_fetch.server_id, bans));
+        });
+    }
+    fn get_audit_log(&self, audit_log_fetch: GetAuditLog) {
+        let client = self.client.clone();
+        let cache = self.cache_writer();
+        self.tokio.spawn(async move {
+            let audit_log = twilight_client::get_audit_log(
+                &client,
+                audit_log_fetch.server_id,
+                audit_log_fetch.action_type,
+                audit_log_fetch.user_id,
+                audit_log_fetch.limit
+            ).await;
+            cache.audit_log.store(audit_log.map(|audit_log| (audit_log_fetch.server_id, audit_log)));
+        });
+    }
+}
+
+// What the tasks get instead of the cache itself, the UI repaints once a task is done with it
+struct CacheWriter {
+    cache: Arc<SharedCache>,
+    ctx: egui::Context,
+}
+impl Deref for CacheWriter {
+    type Target = SharedCache;
+    fn deref(&self) -> &SharedCache {
+        &self.cache
+    }
+}
+impl CacheWriter {
+    // For tasks that change the cache more than once
+    fn repaint(&self) {
+        self.ctx.request_repaint();
+    }
+}
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        self.repaint();
+    }
+}
+
+fn find_channel_mut(channels: &mut (Vec<Channel>, Vec<Channel>), channel_id: u64) -> Option<&mut Channel> {
+    channels.0.iter_mut()
+        .chain(channels.1.iter_mut())
+        .find(|channel| channel.id == channel_id)
+}
+fn replace_channel(channels: &mut (Vec<Channel>, Vec<Channel>), updated: Channel) {
+    if let Some(channel) = find_channel_mut(channels, updated.id.get()) {
+        *channel = updated;
+    }
+}
+// Newer ids replace older ones, mentions already found are kept
+fn record_activity(activity: &mut HashMap<u64, ChannelActivity>, server_id: u64, text_channels: &[Channel]) {
+    for channel in text_channels {
+        let last_message_id = channel.last_message_id.map_or(0, |id| id.get());
+        let entry = activity.entry(channel.id.get()).or_insert_with(|| ChannelActivity {
+            server_id,
+            last_message_id,
+            mention_ids: vec![],
+        });
+        entry.last_message_id = entry.last_message_id.max(last_message_id);
+    }
+}
+// Messages received while fetching may have moved a channel further than what was fetched
+fn merge_activity(activity: &mut HashMap<u64, ChannelActivity>, fetched: HashMap<u64, ChannelActivity>) {
+    for (channel_id, fetched) in fetched {
+        let Some(entry) = activity.get_mut(&channel_id) else {
+            activity.insert(channel_id, fetched);
+            continue;
+        };
+        entry.last_message_id = entry.last_message_id.max(fetched.last_message_id);
+        for mention_id in fetched.mention_ids {
+            if !entry.mention_ids.contains(&mention_id) {
+                entry.mention_ids.push(mention_id);
+            }
+        }
+    }
+}
+// Only for channels whose server is known
+fn record_message(activity: &mut HashMap<u64, ChannelActivity>, msg: &Message) {
+    if let Some(entry) = activity.get_mut(&msg.channel_id.get()) {
+        entry.last_message_id = entry.last_message_id.max(msg.id.get());
+    }
 }
 
 //non-async ticker/scheduler
@@ -302,4 +911,17 @@ impl Ticker {
         }
         return false;
     }
-}
\ No newline at end of file
+}
+
+// Directories and unreadable files are skipped
+fn read_file(path: PathBuf) -> Option<FileUpload> {
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+    let file = File::open(&path).ok()?;
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+    let mut reader = BufReader::new(file);
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).ok()?;
+    Some(FileUpload::new(file_name, bytes))
+}
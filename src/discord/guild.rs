@@ -1,12 +1,63 @@
 use serde::{Deserialize, Deserializer, Serialize};
 use twilight_model::channel::Channel;
-use twilight_model::guild::{GuildPreview, Permissions};
+use twilight_model::guild::{GuildPreview, Member, Permissions, PremiumTier, Role};
 use twilight_model::id::Id;
 use twilight_model::id::marker::GuildMarker;
 use twilight_model::user::CurrentUserGuild;
 use twilight_model::util::ImageHash;
 use twilight_util::snowflake::Snowflake;
 
+// Also the limit in DMs
+pub const BASE_UPLOAD_LIMIT: u64 = 10 * 1024 * 1024;
+
+pub const PERMISSION_NAMES: [(&str, Permissions); 45] = [
+    ("Create invite", Permissions::CREATE_INVITE),
+    ("Kick members", Permissions::KICK_MEMBERS),
+    ("Ban members", Permissions::BAN_MEMBERS),
+    ("Administrator", Permissions::ADMINISTRATOR),
+    ("Manage channels", Permissions::MANAGE_CHANNELS),
+    ("Manage server", Permissions::MANAGE_GUILD),
+    ("Add reactions", Permissions::ADD_REACTIONS),
+    ("View audit log", Permissions::VIEW_AUDIT_LOG),
+    ("Priority speaker", Permissions::PRIORITY_SPEAKER),
+    ("Video", Permissions::STREAM),
+    ("View channels", Permissions::VIEW_CHANNEL),
+    ("Send messages", Permissions::SEND_MESSAGES),
+    ("Send TTS messages", Permissions::SEND_TTS_MESSAGES),
+    ("Manage messages", Permissions::MANAGE_MESSAGES),
+    ("Embed links", Permissions::EMBED_LINKS),
+    ("Attach files", Permissions::ATTACH_FILES),
+    ("Read message history", Permissions::READ_MESSAGE_HISTORY),
+    ("Mention everyone", Permissions::MENTION_EVERYONE),
+    ("Use external emojis", Permissions::USE_EXTERNAL_EMOJIS),
+    ("View server insights", Permissions::VIEW_GUILD_INSIGHTS),
+    ("Connect", Permissions::CONNECT),
+    ("Speak", Permissions::SPEAK),
+    ("Mute members", Permissions::MUTE_MEMBERS),
+    ("Deafen members", Permissions::DEAFEN_MEMBERS),
+    ("Move members", Permissions::MOVE_MEMBERS),
+    ("Use voice activity", Permissions::USE_VAD),
+    ("Change nickname", Permissions::CHANGE_NICKNAME),
+    ("Manage nicknames", Permissions::MANAGE_NICKNAMES),
+    ("Manage roles", Permissions::MANAGE_ROLES),
+    ("Manage webhooks", Permissions::MANAGE_WEBHOOKS),
+    ("Manage expressions", Permissions::MANAGE_GUILD_EXPRESSIONS),
+    ("Use application commands", Permissions::USE_SLASH_COMMANDS),
+    ("Request to speak", Permissions::REQUEST_TO_SPEAK),
+    ("Manage events", Permissions::MANAGE_EVENTS),
+    ("Manage threads", Permissions::MANAGE_THREADS),
+    ("Create public threads", Permissions::CREATE_PUBLIC_THREADS),
+    ("Create private threads", Permissions::CREATE_PRIVATE_THREADS),
+    ("Use external stickers", Permissions::USE_EXTERNAL_STICKERS),
+    ("Send messages in threads", Permissions::SEND_MESSAGES_IN_THREADS),
+    ("Use activities", Permissions::USE_EMBEDDED_ACTIVITIES),
+    ("Timeout members", Permissions::MODERATE_MEMBERS),
+    ("View monetization analytics", Permissions::VIEW_CREATOR_MONETIZATION_ANALYTICS),
+    ("Use soundboard", Permissions::USE_SOUNDBOARD),
+    ("Use external sounds", Permissions::USE_EXTERNAL_SOUNDS),
+    ("Send voice messages", Permissions::SEND_VOICE_MESSAGES),
+];
+
 #[derive(Debug, Clone)]
 pub struct Server {
     pub id: u64,
@@ -20,6 +71,8 @@ pub struct Server {
     pub voice_channels: Vec<Channel>,
 
     pub preview: Option<GuildPreview>,
+    pub roles: Vec<Role>, // sorted by position, highest first
+    pub premium_tier: Option<PremiumTier>, // None until the full guild is fetched
 }
 
 impl Server{
@@ -34,11 +87,43 @@ impl Server{
             text_channels: vec![],
             voice_channels: vec![],
             preview: None,
+            roles: vec![],
+            premium_tier: None,
         }
     }
     pub fn prefetch(&self) {
     }
 
+    pub fn set_roles(&mut self, mut roles: Vec<Role>) {
+        roles.sort_by(|a, b| b.position.cmp(&a.position).then(a.id.cmp(&b.id)));
+        self.roles = roles;
+    }
+
+    pub fn role(&self, role_id: u64) -> Option<&Role> {
+        self.roles.iter().find(|role| role.id == role_id)
+    }
+
+    // The highest hoisted role decides which group a member is listed under
+    pub fn hoisted_role(&self, member: &Member) -> Option<&Role> {
+        self.roles.iter().find(|role| role.hoist && member.roles.contains(&role.id))
+    }
+
+    // The highest role with a color, 0 if none
+    pub fn member_color(&self, member: &Member) -> u32 {
+        self.roles.iter()
+            .find(|role| role.color != 0 && member.roles.contains(&role.id))
+            .map_or(0, |role| role.color)
+    }
+
+    // Per file, boosts raise the limit from level 2 onwards
+    pub fn upload_limit(&self) -> u64 {
+        match self.premium_tier {
+            Some(PremiumTier::Tier2) => 50 * 1024 * 1024,
+            Some(PremiumTier::Tier3) => 100 * 1024 * 1024,
+            _ => BASE_UPLOAD_LIMIT,
+        }
+    }
+
     pub fn id_marker(&self) -> Id<GuildMarker> {
         Id::new(self.id)
     }
@@ -1,17 +1,50 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use twilight_model::channel::ChannelType;
+use twilight_model::channel::message::{Component, Embed};
+use twilight_model::guild::audit_log::AuditLogEventType;
+use twilight_model::guild::Permissions;
+use twilight_model::http::permission_overwrite::PermissionOverwriteType;
+
 pub enum Job {
     GetServers,
     SelectFile,
+    PasteImage,
+    AttachFiles(AttachFiles),
     GetGuildPreview(GetGuildPreview),
     GetChannels(GetChannels),
     GetMessages(GetMessages),
     GetUserMessages(GetUserMessages),
     GetMembers(GetMembers),
+    SearchMembers(SearchMembers),
+    GetUser(GetUser),
     SendMessage(SendMessage),
     EditMessage(EditMessage),
     DeleteMessage(DeleteMessage),
     SendFile(SendFile),
     CreateChannel(CreateChannel),
     DeleteChannel(DeleteChannel),
+    EditChannel(EditChannel),
+    ReorderChannels(ReorderChannels),
+    EditPermissionOverwrite(EditPermissionOverwrite),
+    DeletePermissionOverwrite(DeletePermissionOverwrite),
+    GetGuild(GetGuild),
+    GetRoles(GetRoles),
+    CreateRole(CreateRole),
+    EditRole(EditRole),
+    DeleteRole(DeleteRole),
+    AddMemberRole(AddMemberRole),
+    RemoveMemberRole(RemoveMemberRole),
+    KickMember(KickMember),
+    BanMember(BanMember),
+    TimeoutMember(TimeoutMember),
+    UnbanMember(UnbanMember),
+    GetBans(GetBans),
+    GetAuditLog(GetAuditLog),
+    Download(Download),
+    GetActivity(GetActivity),
 }
 impl Job{
 
@@ -50,7 +83,7 @@ impl GetUserMessages {
 
 pub struct GetMembers {
     pub server_id: u64,
-    pub limit: u16,
+    pub limit: u16, // per page, all pages are fetched
 }
 impl GetMembers {
     pub fn new(server_id: u64, limit: u16) -> Self {
@@ -58,14 +91,36 @@ impl GetMembers {
     }
 }
 
+pub struct SearchMembers {
+    pub server_id: u64,
+    pub query: String,
+    pub limit: u16,
+}
+impl SearchMembers {
+    pub fn new(server_id: u64, query: String, limit: u16) -> Self {
+        Self{ server_id, query, limit }
+    }
+}
+
+pub struct GetUser {
+    pub user_id: u64,
+}
+impl GetUser {
+    pub fn new(user_id: u64) -> Self {
+        Self{ user_id }
+    }
+}
+
 pub struct SendMessage {
     pub channel_id: u64,
     pub content: String,
     pub reply_id: Option<u64>,
+    pub embeds: Vec<Embed>,
+    pub components: Vec<Component>,
 }
 impl SendMessage {
     pub fn new(channel_id: u64, content: String, reply_id: Option<u64>) -> Self {
-        Self{ channel_id, content, reply_id}
+        Self{ channel_id, content, reply_id, embeds: vec![], components: vec![] }
     }
 }
 
@@ -90,24 +145,53 @@ impl DeleteMessage {
     }
 }
 
+pub struct AttachFiles {
+    pub paths: Vec<PathBuf>,
+}
+impl AttachFiles {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self{ paths }
+    }
+}
+
+static NEXT_UPLOAD_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone)]
+pub struct FileUpload {
+    pub id: u64, // unique while the app runs, files can share a name and size
+    pub filename: String,
+    pub bytes: Arc<[u8]>, // shared with the preview and every copy of the queue
+    pub spoiler: bool,
+    pub description: String, // alt text
+}
+impl FileUpload {
+    pub fn new(filename: String, bytes: Vec<u8>) -> Self {
+        let id = NEXT_UPLOAD_ID.fetch_add(1, Ordering::Relaxed);
+        Self{ id, filename, bytes: bytes.into(), spoiler: false, description: "".into() }
+    }
+}
+
 pub struct SendFile {
     pub channel_id: u64,
-    pub filename: String,
-    pub bytes: Vec<u8>
+    pub content: String,
+    pub files: Vec<FileUpload>,
+    pub reply_id: Option<u64>,
 }
 impl SendFile {
-    pub fn new(channel_id: u64, filename: String, bytes: Vec<u8>) -> Self {
-        Self{ channel_id, filename, bytes }
+    pub fn new(channel_id: u64, content: String, files: Vec<FileUpload>, reply_id: Option<u64>) -> Self {
+        Self{ channel_id, content, files, reply_id }
     }
 }
 
 pub struct CreateChannel {
     pub server_id: u64,
     pub name: String,
+    pub kind: ChannelType,
+    pub parent_id: Option<u64>,
 }
 impl CreateChannel {
-    pub fn new(server_id: u64, name: String) -> Self {
-        Self{ server_id, name }
+    pub fn new(server_id: u64, name: String, kind: ChannelType, parent_id: Option<u64>) -> Self {
+        Self{ server_id, name, kind, parent_id }
     }
 }
 
@@ -120,6 +204,66 @@ impl DeleteChannel {
     }
 }
 
+// Fields which don't apply to the channel's type are left as None
+pub struct EditChannel {
+    pub channel_id: u64,
+    pub name: String,
+    pub topic: Option<String>,
+    pub rate_limit_per_user: Option<u16>,
+    pub nsfw: Option<bool>,
+    pub bitrate: Option<u32>,
+    pub user_limit: Option<u16>,
+    pub parent_id: Option<u64>, // None moves the channel out of its category
+}
+impl EditChannel {
+    pub fn new(channel_id: u64, name: String) -> Self {
+        Self{
+            channel_id,
+            name,
+            topic: None,
+            rate_limit_per_user: None,
+            nsfw: None,
+            bitrate: None,
+            user_limit: None,
+            parent_id: None,
+        }
+    }
+}
+
+pub struct ReorderChannels {
+    pub server_id: u64,
+    pub channels: Vec<(u64, Option<u64>)>, // ids and their category, in their new order
+}
+impl ReorderChannels {
+    pub fn new(server_id: u64, channels: Vec<(u64, Option<u64>)>) -> Self {
+        Self{ server_id, channels }
+    }
+}
+
+pub struct EditPermissionOverwrite {
+    pub channel_id: u64,
+    pub target_id: u64,
+    pub kind: PermissionOverwriteType,
+    pub allow: Permissions,
+    pub deny: Permissions,
+}
+impl EditPermissionOverwrite {
+    pub fn new(channel_id: u64, target_id: u64, kind: PermissionOverwriteType, allow: Permissions, deny: Permissions) -> Self {
+        Self{ channel_id, target_id, kind, allow, deny }
+    }
+}
+
+pub struct DeletePermissionOverwrite {
+    pub channel_id: u64,
+    pub target_id: u64,
+    pub kind: PermissionOverwriteType,
+}
+impl DeletePermissionOverwrite {
+    pub fn new(channel_id: u64, target_id: u64, kind: PermissionOverwriteType) -> Self {
+        Self{ channel_id, target_id, kind }
+    }
+}
+
 pub struct GetGuildPreview {
     pub server_id: u64,
 }
@@ -127,4 +271,173 @@ impl GetGuildPreview {
     pub fn new(server_id: u64) -> Self {
         Self{ server_id }
     }
-}
\ No newline at end of file
+}
+pub struct GetGuild {
+    pub server_id: u64,
+}
+impl GetGuild {
+    pub fn new(server_id: u64) -> Self {
+        Self{ server_id }
+    }
+}
+
+pub struct GetRoles {
+    pub server_id: u64,
+}
+impl GetRoles {
+    pub fn new(server_id: u64) -> Self {
+        Self{ server_id }
+    }
+}
+
+pub struct CreateRole {
+    pub server_id: u64,
+    pub name: String,
+    pub color: u32,
+    pub permissions: Permissions,
+    pub hoist: bool,
+    pub mentionable: bool,
+}
+impl CreateRole {
+    pub fn new(server_id: u64, name: String, color: u32, permissions: Permissions, hoist: bool, mentionable: bool) -> Self {
+        Self{ server_id, name, color, permissions, hoist, mentionable }
+    }
+}
+
+pub struct EditRole {
+    pub server_id: u64,
+    pub role_id: u64,
+    pub name: String,
+    pub color: u32,
+    pub permissions: Permissions,
+    pub hoist: bool,
+    pub mentionable: bool,
+}
+impl EditRole {
+    pub fn new(server_id: u64, role_id: u64, name: String, color: u32, permissions: Permissions, hoist: bool, mentionable: bool) -> Self {
+        Self{ server_id, role_id, name, color, permissions, hoist, mentionable }
+    }
+}
+
+pub struct DeleteRole {
+    pub server_id: u64,
+    pub role_id: u64,
+}
+impl DeleteRole {
+    pub fn new(server_id: u64, role_id: u64) -> Self {
+        Self{ server_id, role_id }
+    }
+}
+
+pub struct AddMemberRole {
+    pub server_id: u64,
+    pub user_id: u64,
+    pub role_id: u64,
+}
+impl AddMemberRole {
+    pub fn new(server_id: u64, user_id: u64, role_id: u64) -> Self {
+        Self{ server_id, user_id, role_id }
+    }
+}
+
+pub struct RemoveMemberRole {
+    pub server_id: u64,
+    pub user_id: u64,
+    pub role_id: u64,
+}
+impl RemoveMemberRole {
+    pub fn new(server_id: u64, user_id: u64, role_id: u64) -> Self {
+        Self{ server_id, user_id, role_id }
+    }
+}
+
+// An empty reason isn't sent to the audit log
+pub struct KickMember {
+    pub server_id: u64,
+    pub user_id: u64,
+    pub reason: String,
+}
+impl KickMember {
+    pub fn new(server_id: u64, user_id: u64, reason: String) -> Self {
+        Self{ server_id, user_id, reason }
+    }
+}
+
+pub struct BanMember {
+    pub server_id: u64,
+    pub user_id: u64,
+    pub delete_message_days: u8,
+    pub reason: String,
+}
+impl BanMember {
+    pub fn new(server_id: u64, user_id: u64, delete_message_days: u8, reason: String) -> Self {
+        Self{ server_id, user_id, delete_message_days, reason }
+    }
+}
+
+pub struct TimeoutMember {
+    pub server_id: u64,
+    pub user_id: u64,
+    pub duration_secs: u64, // 0 removes the timeout
+    pub reason: String,
+}
+impl TimeoutMember {
+    pub fn new(server_id: u64, user_id: u64, duration_secs: u64, reason: String) -> Self {
+        Self{ server_id, user_id, duration_secs, reason }
+    }
+}
+
+pub struct UnbanMember {
+    pub server_id: u64,
+    pub user_id: u64,
+    pub reason: String,
+}
+impl UnbanMember {
+    pub fn new(server_id: u64, user_id: u64, reason: String) -> Self {
+        Self{ server_id, user_id, reason }
+    }
+}
+
+pub struct GetBans {
+    pub server_id: u64,
+}
+impl GetBans {
+    pub fn new(server_id: u64) -> Self {
+        Self{ server_id }
+    }
+}
+
+pub struct GetAuditLog {
+    pub server_id: u64,
+    pub action_type: Option<AuditLogEventType>,
+    pub user_id: Option<u64>,
+    pub limit: u16,
+}
+impl GetAuditLog {
+    pub fn new(server_id: u64, action_type: Option<AuditLogEventType>, user_id: Option<u64>, limit: u16) -> Self {
+        Self{ server_id, action_type, user_id, limit }
+    }
+}
+
+pub struct Download {
+    pub url: String,
+    pub filename: String,
+    pub size: u64,
+    pub directory: PathBuf,
+}
+impl Download {
+    pub fn new(url: String, filename: String, size: u64, directory: PathBuf) -> Self {
+        Self{ url, filename, size, directory }
+    }
+}
+
+// Unread and mentioned messages in a server's text channels
+pub struct GetActivity {
+    pub server_id: u64,
+    pub last_read: HashMap<u64, u64>, // by channel id
+}
+impl GetActivity {
+    pub fn new(server_id: u64, last_read: HashMap<u64, u64>) -> Self {
+        Self{ server_id, last_read }
+    }
+}
@@ -1,32 +1,128 @@
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::ops::Deref;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
 use twilight_model::channel::{Channel, Message};
-use twilight_model::guild::Member;
+use twilight_model::guild::{Ban, Member};
+use twilight_model::guild::audit_log::AuditLog;
+use twilight_model::user::User;
+use crate::discord::download::DownloadProgress;
 use crate::discord::guild::Server;
+use crate::discord::jobs::FileUpload;
 
+// What's fetched from Discord is published as snapshots the UI reads without waiting,
+// downloads report their progress behind mutexes
 pub struct SharedCache {
-    pub servers: ArcMutex<Vec<Server>>,
-    pub channels: ArcMutex<(Vec<Channel>, Vec<Channel>)>, // should be cached per server
+    pub servers: Snapshot<Vec<Server>>,
+    pub channels: Snapshot<(Vec<Channel>, Vec<Channel>)>, // should be cached per server
+    pub categories: Snapshot<Vec<Channel>>,
 
-    pub messages: ArcMutex<Vec<Message>>, // should be cached per channel
-    pub members: ArcMutex<Vec<Member>>, // should be cached per server
+    pub messages: Snapshot<Vec<Message>>, // should be cached per channel
+    pub members: Snapshot<Vec<Member>>, // should be cached per server
+    pub member_search: Snapshot<Vec<Member>>,
+    pub user_profile: Snapshot<Option<User>>,
+    // with the server they were fetched for
+    pub bans: Snapshot<(u64, Vec<Ban>)>,
+    pub audit_log: Snapshot<Option<(u64, AuditLog)>>,
+    pub activity: Snapshot<HashMap<u64, ChannelActivity>>, // by channel id
+    pub channel_error: Snapshot<Option<(u64, String)>>, // the channel whose edit failed, and why
 
-    pub file_bytes: ArcMutex<Vec<u8>>,
-    pub file_name: ArcMutex<String>,
+    pub pending_files: Snapshot<Vec<FileUpload>>, // queued in the composer
+    pub upload: Snapshot<UploadStatus>,
+    pub downloads: ArcMutex<Vec<ArcMutex<DownloadProgress>>>, // copied out before they're drawn
 
-    pub rendered_msg_ids: ArcMutex<Vec<u64>>, // cache for UI
+    pub loaded_media: Snapshot<HashSet<String>>, // let through the media policy once
     // config: Config,
     // Temporary?
 }
 impl SharedCache {
     pub fn new() -> Self{
         Self{
-            servers: ArcMutex::new(vec![]),
-            channels: ArcMutex::new((vec![], vec![])),
-            messages: ArcMutex::new(vec![]),
-            members: ArcMutex::new(vec![]),
-            file_bytes: ArcMutex::new(vec![]),
-            rendered_msg_ids: ArcMutex::new(vec![]),
-            file_name: ArcMutex::new("".into()),
+            servers: Snapshot::new(vec![]),
+            channels: Snapshot::new((vec![], vec![])),
+            categories: Snapshot::new(vec![]),
+            messages: Snapshot::new(vec![]),
+            members: Snapshot::new(vec![]),
+            member_search: Snapshot::new(vec![]),
+            user_profile: Snapshot::new(None),
+            bans: Snapshot::new((0, vec![])),
+            audit_log: Snapshot::new(None),
+            activity: Snapshot::new(HashMap::new()),
+            channel_error: Snapshot::new(None),
+            pending_files: Snapshot::new(vec![]),
+            upload: Snapshot::new(UploadStatus::Idle),
+            loaded_media: Snapshot::new(HashSet::new()),
+            downloads: ArcMutex::new(vec![]),
+        }
+    }
+}
+
+// The UI's copies of the snapshots, refreshed at the start of every frame
+pub struct CacheView {
+    pub servers: Latest<Vec<Server>>,
+    pub channels: Latest<(Vec<Channel>, Vec<Channel>)>,
+    pub categories: Latest<Vec<Channel>>,
+    pub messages: Latest<Vec<Message>>,
+    pub members: Latest<Vec<Member>>,
+    pub member_search: Latest<Vec<Member>>,
+    pub user_profile: Latest<Option<User>>,
+    pub bans: Latest<(u64, Vec<Ban>)>,
+    pub audit_log: Latest<Option<(u64, AuditLog)>>,
+    pub activity: Latest<HashMap<u64, ChannelActivity>>,
+}
+impl CacheView {
+    pub fn new(cache: &SharedCache) -> Self {
+        Self{
+            servers: cache.servers.latest(),
+            channels: cache.channels.latest(),
+            categories: cache.categories.latest(),
+            messages: cache.messages.latest(),
+            members: cache.members.latest(),
+            member_search: cache.member_search.latest(),
+            user_profile: cache.user_profile.latest(),
+            bans: cache.bans.latest(),
+            audit_log: cache.audit_log.latest(),
+            activity: cache.activity.latest(),
+        }
+    }
+
+    // Returns true if anything changed since the last refresh
+    pub fn refresh(&mut self, cache: &SharedCache) -> bool {
+        let mut changed = cache.servers.refresh(&mut self.servers);
+        changed |= cache.channels.refresh(&mut self.channels);
+        changed |= cache.categories.refresh(&mut self.categories);
+        changed |= cache.messages.refresh(&mut self.messages);
+        changed |= cache.members.refresh(&mut self.members);
+        changed |= cache.member_search.refresh(&mut self.member_search);
+        changed |= cache.user_profile.refresh(&mut self.user_profile);
+        changed |= cache.bans.refresh(&mut self.bans);
+        changed |= cache.audit_log.refresh(&mut self.audit_log);
+        changed |= cache.activity.refresh(&mut self.activity);
+        changed
+    }
+}
+
+// The newest message of a text channel, compared against the last one read
+#[derive(Clone)]
+pub struct ChannelActivity {
+    pub server_id: u64,
+    pub last_message_id: u64,
+    pub mention_ids: Vec<u64>, // messages mentioning the bot, its roles or everyone
+}
+
+#[derive(Clone)]
+pub enum UploadStatus {
+    Idle,
+    Uploading(usize, u64, u64), // files, bytes sent and the size of the request
+    Failed(String),
+}
+impl UploadStatus {
+    // A failure stays shown until the queued files change
+    pub fn queue_changed(&mut self) {
+        if matches!(self, UploadStatus::Failed(_)) {
+            *self = UploadStatus::Idle;
         }
     }
 }
@@ -58,6 +154,116 @@ impl<T> Clone for ArcMutex<T> {
     }
 }
 
+// Swap-on-write value: writers change a copy and publish it in one atomic pointer swap,
+// readers hold on to the Arc they got and never see a half-done update.
+// Readers never lock or wait, a writer waits out the loads in progress before releasing the old value
+pub struct Snapshot<T> {
+    current: AtomicPtr<T>, // from Arc::into_raw, holds one strong count
+    loading: AtomicUsize, // readers between reading the pointer and taking their count
+    writer: Mutex<()>, // updates are serialized so none overwrites another
+    version: AtomicU64,
+    owns: PhantomData<Arc<T>>, // Send and Sync only where Arc<T> is
+}
+impl<T: Clone> Snapshot<T> {
+    pub fn new(value: T) -> Self {
+        Self{
+            current: AtomicPtr::new(Arc::into_raw(Arc::new(value)) as *mut T),
+            loading: AtomicUsize::new(0),
+            writer: Mutex::new(()),
+            version: AtomicU64::new(0),
+            owns: PhantomData,
+        }
+    }
+
+    pub fn load(&self) -> Arc<T> {
+        self.loading.fetch_add(1, Ordering::SeqCst);
+        let ptr = self.current.load(Ordering::SeqCst);
+        // SAFETY: the pointer came from Arc::into_raw and publish doesn't release it while loading is non-zero
+        let value = unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        };
+        self.loading.fetch_sub(1, Ordering::SeqCst);
+        value
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    pub fn store(&self, value: T) {
+        let _writer = lock(&self.writer);
+        self.publish(value);
+    }
+
+    // The copy is made and changed without blocking readers
+    pub fn update<R>(&self, change: impl FnOnce(&mut T) -> R) -> R {
+        let _writer = lock(&self.writer);
+        let mut value = (*self.load()).clone();
+        let result = change(&mut value);
+        self.publish(value);
+        result
+    }
+
+    pub fn latest(&self) -> Latest<T> {
+        // the version is read first, a newer value only causes one extra reload
+        let version = self.version();
+        Latest{ value: self.load(), version }
+    }
+
+    // Only touches the lock if a new value was published, returns true if it was
+    pub fn refresh(&self, latest: &mut Latest<T>) -> bool {
+        if self.version() == latest.version {
+            return false;
+        }
+        *latest = self.latest();
+        true
+    }
+
+    fn publish(&self, value: T) {
+        let old = self.current.swap(Arc::into_raw(Arc::new(value)) as *mut T, Ordering::SeqCst);
+        // a load that started before the swap may still be about to count the old value,
+        // any that starts after it gets the new one
+        while self.loading.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        // SAFETY: swapped out, so this is the strong count the snapshot held
+        drop(unsafe { Arc::from_raw(old) });
+        // bumped after the swap, so a reader that sees the new version gets the new value
+        self.version.fetch_add(1, Ordering::Release);
+    }
+}
+impl<T> Drop for Snapshot<T> {
+    fn drop(&mut self) {
+        // SAFETY: nothing else can load while it's being dropped
+        drop(unsafe { Arc::from_raw(*self.current.get_mut()) });
+    }
+}
+
+pub struct Latest<T> {
+    value: Arc<T>,
+    version: u64,
+}
+impl<T> Latest<T> {
+    // Shared so it can outlive a borrow of the view
+    pub fn get(&self) -> Arc<T> {
+        self.value.clone()
+    }
+}
+impl<T> Deref for Latest<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
 #[derive(Debug)]
 pub struct Queue<T>{
     pub vec: Vec<T>,
@@ -84,4 +290,41 @@ impl<T> Queue<T>{
     pub fn is_empty(&self) -> bool {
         self.vec.is_empty()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_keep_their_snapshot() {
+        let snapshot = Snapshot::new(vec![1]);
+        let mut latest = snapshot.latest();
+        let held = latest.get();
+        snapshot.update(|values| values.push(2));
+        assert_eq!(*held, vec![1]);
+        assert!(snapshot.refresh(&mut latest));
+        assert_eq!(*latest, vec![1, 2]);
+        assert!(!snapshot.refresh(&mut latest));
+    }
+
+    #[test]
+    fn concurrent_loads_see_whole_values() {
+        let snapshot = Arc::new(Snapshot::new(vec![0u64; 64]));
+        let readers: Vec<_> = (0..4).map(|_| {
+            let snapshot = snapshot.clone();
+            thread::spawn(move || {
+                for _ in 0..10_000 {
+                    let values = snapshot.load();
+                    assert!(values.iter().all(|value| *value == values[0]));
+                }
+            })
+        }).collect();
+        for round in 1..=1_000 {
+            snapshot.store(vec![round; 64]);
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(snapshot.load()[0], 1_000);
+    }
+}
@@ -1,10 +1,18 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 use twilight_http::Client;
+use twilight_http::request::AuditLogReason;
+use twilight_http::request::guild::update_guild_channel_positions::Position;
 use twilight_model::channel::{Channel, ChannelType, Message};
-use twilight_model::guild::{GuildPreview, Member};
-use twilight_model::http::attachment::Attachment;
+use twilight_model::channel::message::{Component, Embed};
+use twilight_model::guild::{Ban, GuildPreview, Member, PremiumTier, Role};
+use twilight_model::guild::audit_log::{AuditLog, AuditLogEventType};
+use twilight_model::http::permission_overwrite::{PermissionOverwrite, PermissionOverwriteType};
 use twilight_model::id::Id;
-use twilight_model::user::CurrentUserGuild;
+use twilight_model::user::{CurrentUser, CurrentUserGuild, User};
+use twilight_model::util::Timestamp;
 use crate::discord::guild::Server;
+use crate::discord::jobs::{CreateRole, EditChannel, EditPermissionOverwrite, EditRole};
 
 pub async fn test(token: String) {
     let client = Client::builder().token(token).build();
@@ -39,14 +47,40 @@ pub async fn get_channels(client: &Client, server_id: u64) -> Vec<Channel> {
     serde_json::from_str(channels_body.as_str()).expect(INSTANCE_ERR)
 }
 
-pub async fn get_members(client: &Client, guild_id: u64, limit: u16) -> Vec<Member> {
-    let response = client.guild_members(Id::new(guild_id))
+// Members are sorted by user id, `after` is the last user id of the previous page
+pub async fn get_members(client: &Client, guild_id: u64, after: Option<u64>, limit: u16) -> Vec<Member> {
+    let mut request = client.guild_members(Id::new(guild_id))
+        .limit(limit).expect(LIMIT_ERR);
+    if let Some(after) = after {
+        request = request.after(Id::new(after));
+    }
+    let result_response = request.await;
+    if result_response.is_err() {
+        return Vec::new();
+    }
+    let members_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    serde_json::from_str(members_body.as_str()).expect(INSTANCE_ERR)
+}
+pub async fn search_members(client: &Client, guild_id: u64, query: &str, limit: u16) -> Vec<Member> {
+    let result_response = client.search_guild_members(Id::new(guild_id), query)
         .limit(limit).expect(LIMIT_ERR)
-        .await.unwrap();
-    let members_body = response.text().await.expect(RESPONSE_BODY_ERR);
+        .await;
+    if result_response.is_err() {
+        return Vec::new();
+    }
+    let members_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
     serde_json::from_str(members_body.as_str()).expect(INSTANCE_ERR)
 }
 
+// Only a full user fetch includes the banner and accent color
+pub async fn get_user(client: &Client, user_id: u64) -> Option<User> {
+    let result_response = client.user(Id::new(user_id)).await;
+    if result_response.is_err() {
+        return None;
+    }
+    let user_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    serde_json::from_str(user_body.as_str()).ok()
+}
 pub async fn get_messages(client: &Client, channel_id: u64, limit: u16) -> Vec<Message>{
     let result_response = client.channel_messages(Id::new(channel_id))
         .limit(limit).expect(LIMIT_ERR)
@@ -57,9 +91,42 @@ pub async fn get_messages(client: &Client, channel_id: u64, limit: u16) -> Vec<M
     let messages_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
     serde_json::from_str(messages_body.as_str()).expect(INSTANCE_ERR)
 }
-pub async fn create_channel(client: &Client, guild_id: u64, name: String) -> Option<Channel> {
-    let result_response = client.create_guild_channel(Id::new(guild_id), name.as_str())
-        .expect(VALIDATION_ERR).await;
+// At most `limit` of the messages sent after `after`, newest first
+pub async fn get_messages_after(client: &Client, channel_id: u64, after: u64, limit: u16) -> Vec<Message>{
+    let result_response = client.channel_messages(Id::new(channel_id))
+        .after(Id::new(after))
+        .limit(limit).expect(LIMIT_ERR)
+        .await;
+    if result_response.is_err() {
+        return Vec::new();
+    }
+    let messages_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    serde_json::from_str(messages_body.as_str()).expect(INSTANCE_ERR)
+}
+pub async fn get_current_user(client: &Client) -> Option<CurrentUser> {
+    let result_response = client.current_user().await;
+    if result_response.is_err() {
+        return None;
+    }
+    let user_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    serde_json::from_str(user_body.as_str()).ok()
+}
+pub async fn get_member(client: &Client, guild_id: u64, user_id: u64) -> Option<Member> {
+    let result_response = client.guild_member(Id::new(guild_id), Id::new(user_id)).await;
+    if result_response.is_err() {
+        return None;
+    }
+    let member_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    serde_json::from_str(member_body.as_str()).ok()
+}
+pub async fn create_channel(client: &Client, guild_id: u64, name: String, kind: ChannelType, parent_id: Option<u64>) -> Option<Channel> {
+    let mut new_channel = client.create_guild_channel(Id::new(guild_id), name.as_str())
+        .ok()?
+        .kind(kind);
+    if let Some(id) = parent_id {
+        new_channel = new_channel.parent_id(Id::new(id));
+    }
+    let result_response = new_channel.await;
     if result_response.is_err() {
         return None;
     }
@@ -74,15 +141,199 @@ pub async fn delete_channel(client: &Client, channel_id: u64) -> Option<Channel>
     let response_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
     Some(serde_json::from_str(response_body.as_str()).expect(INSTANCE_ERR))
 }
-pub async fn send_message(client: &Client, channel_id: u64, content: &str, reply_id: Option<u64>) -> Message {
+pub async fn edit_channel(client: &Client, edit: &EditChannel) -> Result<Channel, String> {
+    let mut update = client.update_channel(Id::new(edit.channel_id))
+        .name(edit.name.as_str()).map_err(|err| err.to_string())?
+        .parent_id(edit.parent_id.map(Id::new));
+    if let Some(topic) = &edit.topic {
+        update = update.topic(topic.as_str()).map_err(|err| err.to_string())?;
+    }
+    if let Some(slowmode) = edit.rate_limit_per_user {
+        update = update.rate_limit_per_user(slowmode).map_err(|err| err.to_string())?;
+    }
+    if let Some(nsfw) = edit.nsfw {
+        update = update.nsfw(nsfw);
+    }
+    if let Some(bitrate) = edit.bitrate {
+        update = update.bitrate(bitrate).map_err(|err| err.to_string())?;
+    }
+    if let Some(user_limit) = edit.user_limit {
+        update = update.user_limit(user_limit).map_err(|err| err.to_string())?;
+    }
+    let response = update.await.map_err(|err| err.to_string())?;
+    let response_body = response.text().await.expect(RESPONSE_BODY_ERR);
+    Ok(serde_json::from_str(response_body.as_str()).expect(INSTANCE_ERR))
+}
+// positions count up within each category, in the order the channels are given
+pub async fn reorder_channels(client: &Client, guild_id: u64, channels: &[(u64, Option<u64>)]) -> bool {
+    let mut next_positions: HashMap<Option<u64>, u64> = HashMap::new();
+    let positions: Vec<Position> = channels.iter()
+        .map(|(id, parent_id)| {
+            let position = next_positions.entry(*parent_id).or_insert(0);
+            *position += 1;
+            Position::from((Id::new(*id), *position - 1))
+        })
+        .collect();
+    client.update_guild_channel_positions(Id::new(guild_id), &positions).await.is_ok()
+}
+pub async fn edit_permission_overwrite(client: &Client, edit: &EditPermissionOverwrite) -> bool {
+    let overwrite = PermissionOverwrite {
+        allow: Some(edit.allow),
+        deny: Some(edit.deny),
+        id: Id::new(edit.target_id),
+        kind: edit.kind,
+    };
+    client.update_channel_permission(Id::new(edit.channel_id), &overwrite).await.is_ok()
+}
+pub async fn delete_permission_overwrite(client: &Client, channel_id: u64, target_id: u64, kind: PermissionOverwriteType) -> bool {
+    let request = client.delete_channel_permission(Id::new(channel_id));
+    let result_response = match kind {
+        PermissionOverwriteType::Member => request.member(Id::new(target_id)).await,
+        _ => request.role(Id::new(target_id)).await,
+    };
+    result_response.is_ok()
+}
+pub async fn get_roles(client: &Client, guild_id: u64) -> Vec<Role> {
+    let result_response = client.roles(Id::new(guild_id)).await;
+    if result_response.is_err() {
+        return Vec::new();
+    }
+    let roles_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    serde_json::from_str(roles_body.as_str()).expect(INSTANCE_ERR)
+}
+pub async fn create_role(client: &Client, create: &CreateRole) -> Option<Role> {
+    let result_response = client.create_role(Id::new(create.server_id))
+        .name(create.name.as_str())
+        .color(create.color)
+        .permissions(create.permissions)
+        .hoist(create.hoist)
+        .mentionable(create.mentionable)
+        .await;
+    if result_response.is_err() {
+        return None;
+    }
+    let response_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    Some(serde_json::from_str(response_body.as_str()).expect(INSTANCE_ERR))
+}
+pub async fn edit_role(client: &Client, edit: &EditRole) -> Option<Role> {
+    let result_response = client.update_role(Id::new(edit.server_id), Id::new(edit.role_id))
+        .name(Some(edit.name.as_str()))
+        .color(Some(edit.color))
+        .permissions(edit.permissions)
+        .hoist(edit.hoist)
+        .mentionable(edit.mentionable)
+        .await;
+    if result_response.is_err() {
+        return None;
+    }
+    let response_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    Some(serde_json::from_str(response_body.as_str()).expect(INSTANCE_ERR))
+}
+pub async fn delete_role(client: &Client, guild_id: u64, role_id: u64) -> bool {
+    client.delete_role(Id::new(guild_id), Id::new(role_id)).await.is_ok()
+}
+pub async fn add_member_role(client: &Client, guild_id: u64, user_id: u64, role_id: u64) -> bool {
+    client.add_guild_member_role(Id::new(guild_id), Id::new(user_id), Id::new(role_id)).await.is_ok()
+}
+pub async fn remove_member_role(client: &Client, guild_id: u64, user_id: u64, role_id: u64) -> bool {
+    client.remove_guild_member_role(Id::new(guild_id), Id::new(user_id), Id::new(role_id)).await.is_ok()
+}
+pub async fn kick_member(client: &Client, guild_id: u64, user_id: u64, reason: &str) -> bool {
+    let mut kick = client.remove_guild_member(Id::new(guild_id), Id::new(user_id));
+    if !reason.is_empty() {
+        kick = match kick.reason(reason) {
+            Ok(kick) => kick,
+            Err(_) => return false,
+        };
+    }
+    kick.await.is_ok()
+}
+pub async fn ban_member(client: &Client, guild_id: u64, user_id: u64, delete_message_days: u8, reason: &str) -> bool {
+    let delete_message_seconds = delete_message_days as u32 * 24 * 60 * 60;
+    let mut ban = client.create_ban(Id::new(guild_id), Id::new(user_id))
+        .delete_message_seconds(delete_message_seconds).expect(VALIDATION_ERR);
+    if !reason.is_empty() {
+        ban = match ban.reason(reason) {
+            Ok(ban) => ban,
+            Err(_) => return false,
+        };
+    }
+    ban.await.is_ok()
+}
+pub async fn unban_member(client: &Client, guild_id: u64, user_id: u64, reason: &str) -> bool {
+    let mut unban = client.delete_ban(Id::new(guild_id), Id::new(user_id));
+    if !reason.is_empty() {
+        unban = match unban.reason(reason) {
+            Ok(unban) => unban,
+            Err(_) => return false,
+        };
+    }
+    unban.await.is_ok()
+}
+// a duration of 0 lifts the timeout
+pub async fn timeout_member(client: &Client, guild_id: u64, user_id: u64, duration_secs: u64, reason: &str) -> Option<Member> {
+    let until = if duration_secs == 0 {
+        None
+    } else {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        Some(Timestamp::from_secs((now + duration_secs) as i64).expect(VALIDATION_ERR))
+    };
+    let mut timeout = client.update_guild_member(Id::new(guild_id), Id::new(user_id))
+        .communication_disabled_until(until).expect(VALIDATION_ERR);
+    if !reason.is_empty() {
+        timeout = match timeout.reason(reason) {
+            Ok(timeout) => timeout,
+            Err(_) => return None,
+        };
+    }
+    let result_response = timeout.await;
+    if result_response.is_err() {
+        return None;
+    }
+    let response_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    Some(serde_json::from_str(response_body.as_str()).expect(INSTANCE_ERR))
+}
+pub async fn get_bans(client: &Client, guild_id: u64) -> Vec<Ban> {
+    let result_response = client.bans(Id::new(guild_id)).await;
+    if result_response.is_err() {
+        return Vec::new();
+    }
+    let bans_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    serde_json::from_str(bans_body.as_str()).expect(INSTANCE_ERR)
+}
+pub async fn get_audit_log(client: &Client, guild_id: u64, action_type: Option<AuditLogEventType>, user_id: Option<u64>, limit: u16) -> Option<AuditLog> {
+    let mut request = client.audit_log(Id::new(guild_id))
+        .limit(limit).expect(LIMIT_ERR);
+    if let Some(action_type) = action_type {
+        request = request.action_type(action_type);
+    }
+    if let Some(user_id) = user_id.and_then(Id::new_checked) {
+        request = request.user_id(user_id);
+    }
+    let result_response = request.await;
+    if result_response.is_err() {
+        return None;
+    }
+    let response_body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    Some(serde_json::from_str(response_body.as_str()).expect(INSTANCE_ERR))
+}
+// Embeds and components come from the builder and may not pass validation
+pub async fn send_message(
+    client: &Client, channel_id: u64, content: &str, reply_id: Option<u64>, embeds: &[Embed], components: &[Component]
+) -> Option<Message> {
     let mut new_msg = client.create_message(Id::new(channel_id))
-        .content(content).expect(VALIDATION_ERR);
+        .content(content).ok()?
+        .embeds(embeds).ok()?
+        .components(components).ok()?;
     if let Some(id) = reply_id {
         new_msg = new_msg.reply(Id::new(id));
     }
-    let response = new_msg.await.unwrap();
-    let body = response.text().await.expect(RESPONSE_BODY_ERR);
-    serde_json::from_str(body.as_str()).expect(INSTANCE_ERR)
+    let result_response = new_msg.await;
+    if result_response.is_err() {
+        return None;
+    }
+    let body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    Some(serde_json::from_str(body.as_str()).expect(INSTANCE_ERR))
 }
 pub async fn delete_message(client: &Client, channel_id: u64, message_id: u64) -> bool {
     let response = client.delete_message(Id::new(channel_id), Id::new(message_id))
@@ -96,13 +347,16 @@ pub async fn edit_message(client: &Client, channel_id: u64, message_id: u64, new
     ).content(Some(new_content.as_str())).expect(VALIDATION_ERR).await.unwrap();
     response.status().is_success()
 }
-pub async fn send_file(client: &Client, channel_id: u64, filename: String, bytes: Vec<u8>) -> Message {
-    let attachment = &[Attachment::from_bytes(filename, bytes, 1)];
-    let response = client.create_message(Id::new(channel_id))
-        .attachments(attachment).expect(VALIDATION_ERR)
-        .await.unwrap();
-    let body = response.text().await.expect(RESPONSE_BODY_ERR);
-    serde_json::from_str(body.as_str()).expect(INSTANCE_ERR)
+// Only the boost level is read, the rest of the guild is fetched elsewhere
+pub async fn get_premium_tier(client: &Client, guild_id: u64) -> Option<PremiumTier> {
+    let result_response = client.guild(Id::new(guild_id)).await;
+    if result_response.is_err() {
+        return None;
+    }
+    let body = result_response.unwrap().text().await.expect(RESPONSE_BODY_ERR);
+    let guild: serde_json::Value = serde_json::from_str(body.as_str()).expect(INSTANCE_ERR);
+    let tier = guild["premium_tier"].as_u64()?;
+    Some(PremiumTier::from(tier as u8))
 }
 pub async fn get_guild_preview(client: &Client, server_id: u64) -> GuildPreview {
     let response = client.guild_preview(Id::new(server_id)).await.unwrap();
@@ -125,8 +379,18 @@ pub fn split_into_text_and_voice(mixed_channels: Vec<Channel>) -> (Vec<Channel>,
             _ => { continue;}
         }
     }
+    text_channels.sort_by_key(|channel| channel.position);
+    voice_channels.sort_by_key(|channel| channel.position);
     return (text_channels, voice_channels);
 }
+pub fn filter_categories(mixed_channels: &[Channel]) -> Vec<Channel> {
+    let mut categories: Vec<Channel> = mixed_channels.iter()
+        .filter(|channel| channel.kind == ChannelType::GuildCategory)
+        .cloned()
+        .collect();
+    categories.sort_by_key(|channel| channel.position);
+    categories
+}
 
 const LIMIT_ERR: &str = "Limit error";
 const VALIDATION_ERR: &str = "Failed to validate";
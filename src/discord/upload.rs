@@ -0,0 +1,163 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use twilight_model::channel::Message;
+use crate::discord::jobs::FileUpload;
+
+// Twilight builds the whole request before handing it over, so it can't tell how much was sent.
+// Messages with files are posted here instead, the multipart body is streamed and counted as it goes.
+
+const API_URL: &str = "https://discord.com/api/v10";
+const USER_AGENT: &str = concat!("DiscordBot (", env!("CARGO_PKG_NAME"), ", ", env!("CARGO_PKG_VERSION"), ")");
+const PROGRESS_STEP: u64 = 64 * 1024; // reported at most this often
+
+// Blocking, meant to be run off the async workers. Spoilers are marked by the SPOILER_ filename prefix.
+// The token is the client's, already prefixed with "Bot "
+pub fn send_files(
+    token: &str, channel_id: u64, content: &str, files: &[FileUpload], reply_id: Option<u64>,
+    progress: impl FnMut(u64, u64),
+) -> Result<Message, String> {
+    twilight_validate::message::content(content).map_err(|err| err.to_string())?;
+    let boundary = boundary();
+    let body = Body::new(&boundary, content, files, reply_id);
+    let total = body.len();
+    let reader = Counting { body, sent: 0, reported: 0, total, progress };
+    let result = ureq::post(&format!("{}/channels/{}/messages", API_URL, channel_id))
+        .set("Authorization", token)
+        .set("User-Agent", USER_AGENT)
+        .set("Content-Type", &format!("multipart/form-data; boundary={}", boundary))
+        .set("Content-Length", &total.to_string())
+        .send(reader);
+    match result {
+        Ok(response) => {
+            let body = response.into_string().map_err(|err| err.to_string())?;
+            serde_json::from_str(&body).map_err(|err| err.to_string())
+        }
+        Err(ureq::Error::Status(status, response)) => Err(api_error(status, response)),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+// Discord explains failures in a "message" field
+fn api_error(status: u16, response: ureq::Response) -> String {
+    let body = response.into_string().unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body).ok()
+        .and_then(|json| json["message"].as_str().map(String::from));
+    match message {
+        Some(message) => format!("{} ({})", message, status),
+        None => format!("Discord responded with {}", status),
+    }
+}
+
+// Long and random enough not to turn up inside the files
+fn boundary() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos());
+    format!("------discord-bot-gui-{:032x}", nanos)
+}
+
+// The parts in order, the files themselves are shared rather than copied
+struct Body {
+    segments: Vec<Arc<[u8]>>,
+    index: usize,
+    offset: usize, // into the current segment
+}
+
+impl Body {
+    fn new(boundary: &str, content: &str, files: &[FileUpload], reply_id: Option<u64>) -> Self {
+        let attachments: Vec<serde_json::Value> = files.iter().enumerate().map(|(id, file)| {
+            let mut attachment = serde_json::json!({ "id": id, "filename": filename(file) });
+            if !file.description.trim().is_empty() {
+                attachment["description"] = file.description.clone().into();
+            }
+            attachment
+        }).collect();
+        let mut payload = serde_json::json!({ "content": content, "attachments": attachments });
+        if let Some(message_id) = reply_id {
+            payload["message_reference"] = serde_json::json!({ "message_id": message_id.to_string() });
+        }
+        let mut segments: Vec<Arc<[u8]>> = vec![format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\nContent-Type: application/json\r\n\r\n{}\r\n",
+            boundary, payload
+        ).into_bytes().into()];
+        for (id, file) in files.iter().enumerate() {
+            // quotes would end the parameter early
+            let name = filename(file).replace('"', "_");
+            segments.push(format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"files[{}]\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                boundary, id, name
+            ).into_bytes().into());
+            segments.push(file.bytes.clone());
+            segments.push(b"\r\n".to_vec().into());
+        }
+        segments.push(format!("--{}--\r\n", boundary).into_bytes().into());
+        Self{ segments, index: 0, offset: 0 }
+    }
+
+    fn len(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.len() as u64).sum()
+    }
+}
+
+impl Read for Body {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while let Some(segment) = self.segments.get(self.index) {
+            let rest = &segment[self.offset..];
+            if rest.is_empty() {
+                self.index += 1;
+                self.offset = 0;
+                continue;
+            }
+            let read = rest.len().min(buffer.len());
+            buffer[..read].copy_from_slice(&rest[..read]);
+            self.offset += read;
+            return Ok(read);
+        }
+        Ok(0)
+    }
+}
+
+fn filename(file: &FileUpload) -> String {
+    if file.spoiler { format!("SPOILER_{}", file.filename) } else { file.filename.clone() }
+}
+
+// Reports what's been handed to the connection so far, and the total
+struct Counting<F: FnMut(u64, u64)> {
+    body: Body,
+    sent: u64,
+    reported: u64,
+    total: u64,
+    progress: F,
+}
+
+impl<F: FnMut(u64, u64)> Read for Counting<F> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.body.read(buffer)?;
+        self.sent += read as u64;
+        if self.sent - self.reported >= PROGRESS_STEP || self.sent == self.total {
+            self.reported = self.sent;
+            (self.progress)(self.sent, self.total);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_streams_every_part() {
+        let mut file = FileUpload::new("cat.png".into(), b"meow".to_vec());
+        file.spoiler = true;
+        let mut body = Body::new("XX", "hi", &[file], None);
+        let expected = body.len();
+        let mut sent = vec![];
+        body.read_to_end(&mut sent).unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert_eq!(sent.len() as u64, expected);
+        assert!(sent.starts_with("--XX\r\n"));
+        assert!(sent.contains("\"filename\":\"SPOILER_cat.png\""));
+        assert!(sent.contains("filename=\"SPOILER_cat.png\"\r\nContent-Type: application/octet-stream\r\n\r\nmeow\r\n"));
+        assert!(sent.ends_with("--XX--\r\n"));
+    }
+}
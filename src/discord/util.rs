@@ -1,10 +1,13 @@
-use egui::Event;
+use std::io::Cursor;
+use egui::{Color32, Event};
 use twilight_model::channel::Message;
+use twilight_model::channel::message::sticker::{MessageSticker, StickerFormatType};
+use twilight_model::guild::Member;
+use twilight_model::user::User;
 
 const CDN_DISCORD_DOMAIN: &str = "https://cdn.discordapp.com";
-const MEDIA_DISCORD_DOMAIN: &str = "https://media.discordapp.com";
-const MEDIA_TENOR: &str = "https://media.tenor.com";
-const SUPPORTED_MEDIA: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+// GIF stickers are only served by the media proxy
+const MEDIA_PROXY_DOMAIN: &str = "https://media.discordapp.net";
 
 pub fn strip_parameters(mut link: String) -> String {
     let index = link.find('?');
@@ -23,24 +26,45 @@ pub fn format_message_no_attachments(msg: &Message) -> String{
 }
 //let text = util::format_message(&msg);
 pub fn format_message(msg: &Message) -> String{
-    return if !msg.content.is_empty() {
-        if msg.attachments.is_empty() {
-            return format!("[{}] {}", msg.author.name, msg.content);
-        }
-        let link = strip_parameters(msg.attachments[0].url.to_owned());
-        format!("[{}] {} {}", msg.author.name, msg.content, link)
-    } else {
-        if msg.attachments.is_empty() {
-            return format!("[{}]", msg.author.name)
-        }
-        let link = strip_parameters(msg.attachments[0].url.to_owned());
-        format!("[{}] {}", msg.author.name, link)
+    let mut text = format!("[{}]", msg.author.name);
+    if !msg.content.is_empty() {
+        text.push(' ');
+        text.push_str(&msg.content);
     }
+    for attachment in &msg.attachments {
+        text.push(' ');
+        text.push_str(&strip_parameters(attachment.url.to_owned()));
+    }
+    text
 }
-pub fn is_domain_trusted(link: &String) -> bool {
-    link.starts_with(CDN_DISCORD_DOMAIN)
-        || link.starts_with(MEDIA_TENOR)
-        || link.starts_with(MEDIA_DISCORD_DOMAIN)
+// Mentions of everyone count too
+pub fn mentions_user(msg: &Message, user_id: u64, role_ids: &[u64]) -> bool {
+    msg.mention_everyone
+        || msg.mentions.iter().any(|mention| mention.id == user_id)
+        || msg.mention_roles.iter().any(|role_id| role_ids.contains(&role_id.get()))
+}
+// 1023 B, 1.5 KB, 8.0 MB
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        return format!("{} B", bytes);
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+// Attachments scaled down by the media proxy, the size is in physical pixels.
+// The signature parameters are kept, the proxy refuses attachments without them.
+pub fn thumbnail_url(url: &str, width: u32, height: u32) -> String {
+    let Some(path) = url.strip_prefix(CDN_DISCORD_DOMAIN) else {
+        return url.to_string();
+    };
+    let separator = if path.contains('?') { '&' } else { '?' };
+    format!("{}{}{}width={}&height={}", MEDIA_PROXY_DOMAIN, path, separator, width.max(1), height.max(1))
 }
 pub fn pasted_image(ctx: &egui::Context) -> bool {
     return ctx.input(|i| {
@@ -59,11 +83,64 @@ pub fn pasted_image(ctx: &egui::Context) -> bool {
         return false;
     });
 }
-pub fn is_supported_media(link: &String) -> bool {
-    for format in SUPPORTED_MEDIA {
-        if link.to_lowercase().ends_with(format) {
-            return true;
-        }
+// RGBA pixels, None if they don't add up to the dimensions
+pub fn encode_png(width: u32, height: u32, rgba: Vec<u8>) -> Option<Vec<u8>> {
+    let image = image::RgbaImage::from_raw(width, height, rgba)?;
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageOutputFormat::Png).ok()?;
+    Some(png.into_inner())
+}
+// By extension, for telling images apart from other files
+pub fn is_image_file(name: &str) -> bool {
+    image::ImageFormat::from_path(name).is_ok()
+}
+// Roles without a color use 0
+pub fn role_color(color: u32) -> Option<Color32> {
+    if color == 0 {
+        return None;
     }
-    return false;
-}
\ No newline at end of file
+    Some(Color32::from_rgb((color >> 16) as u8, (color >> 8) as u8, color as u8))
+}
+// Nickname, then global name, then username
+pub fn display_name(member: &Member) -> &str {
+    member.nick.as_deref()
+        .or(member.user.global_name.as_deref())
+        .unwrap_or(&member.user.name)
+}
+// Server avatar, then user avatar, then the default avatar
+pub fn avatar_url(member: &Member, server_id: u64, size: u16) -> String {
+    match member.avatar {
+        Some(hash) => format!(
+            "{}/guilds/{}/users/{}/avatars/{}.png?size={}", CDN_DISCORD_DOMAIN, server_id, member.user.id, hash, size
+        ),
+        None => user_avatar_url(&member.user, size),
+    }
+}
+pub fn user_avatar_url(user: &User, size: u16) -> String {
+    let user_id = user.id.get();
+    if let Some(hash) = user.avatar {
+        return format!("{}/avatars/{}/{}.png?size={}", CDN_DISCORD_DOMAIN, user_id, hash, size);
+    }
+    // users migrated to unique usernames have a discriminator of 0
+    let index = match user.discriminator {
+        0 => (user_id >> 22) % 6,
+        discriminator => discriminator as u64 % 5,
+    };
+    format!("{}/embed/avatars/{}.png", CDN_DISCORD_DOMAIN, index)
+}
+pub fn banner_url(user: &User, size: u16) -> Option<String> {
+    let hash = user.banner?;
+    Some(format!("{}/banners/{}/{}.png?size={}", CDN_DISCORD_DOMAIN, user.id, hash, size))
+}
+pub fn emoji_url(emoji_id: u64, animated: bool) -> String {
+    let extension = if animated { "gif" } else { "png" };
+    format!("{}/emojis/{}.{}?size=48", CDN_DISCORD_DOMAIN, emoji_id, extension)
+}
+// Lottie stickers can't be rendered
+pub fn sticker_url(sticker: &MessageSticker) -> Option<String> {
+    match sticker.format_type {
+        StickerFormatType::Png | StickerFormatType::Apng => Some(format!("{}/stickers/{}.png", CDN_DISCORD_DOMAIN, sticker.id)),
+        StickerFormatType::Gif => Some(format!("{}/stickers/{}.gif", MEDIA_PROXY_DOMAIN, sticker.id)),
+        _ => None,
+    }
+}
@@ -2,6 +2,8 @@ use std::thread;
 
 mod discord;
 mod config;
+mod media_policy;
+mod time_format;
 mod app;
 mod backend;
 
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+// Which remote media is loaded without asking, configured under "media" in the config file.
+// Hosts are matched on the parsed url, a rule for "discordapp.com" also covers its subdomains.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DomainAction {
+    Autoload,
+    ClickToLoad,
+    Block,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainRule {
+    pub domain: String,
+    pub action: DomainAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MediaPolicy {
+    pub domains: Vec<DomainRule>,
+    pub unlisted: DomainAction, // for hosts without a rule
+    pub extensions: Vec<String>,
+    pub mime_types: Vec<String>, // "image/*" allows the whole type
+}
+
+// How spoilers, and the media of NSFW channels, are shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum RevealMode {
+    Always,
+    Never,
+    #[default]
+    Click,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaDecision {
+    Load,
+    ClickToLoad,
+    Blocked(String), // why
+}
+
+impl Default for MediaPolicy {
+    fn default() -> Self {
+        let autoload = ["cdn.discordapp.com", "media.discordapp.net", "media.discordapp.com", "media.tenor.com"];
+        Self{
+            domains: autoload.iter()
+                .map(|domain| DomainRule { domain: domain.to_string(), action: DomainAction::Autoload })
+                .collect(),
+            unlisted: DomainAction::ClickToLoad,
+            extensions: ["png", "jpg", "jpeg", "gif", "webp"].iter().map(|ext| ext.to_string()).collect(),
+            mime_types: ["image/png", "image/jpeg", "image/gif", "image/webp"].iter().map(|mime| mime.to_string()).collect(),
+        }
+    }
+}
+
+impl MediaPolicy {
+    // The mime type is only known for attachments, urls without an extension need one
+    pub fn decide(&self, link: &str, mime: Option<&str>) -> MediaDecision {
+        let Ok(url) = Url::parse(link) else {
+            return MediaDecision::Blocked("invalid url".into());
+        };
+        if url.scheme() != "https" {
+            return MediaDecision::Blocked(format!("{} isn't https", url.scheme()));
+        }
+        let Some(host) = url.host_str() else {
+            return MediaDecision::Blocked("no host".into());
+        };
+        let host = host.to_lowercase();
+        if let Some(reason) = self.type_rejection(&url, mime) {
+            return MediaDecision::Blocked(reason);
+        }
+        match self.domain_action(&host) {
+            DomainAction::Autoload => MediaDecision::Load,
+            DomainAction::ClickToLoad => MediaDecision::ClickToLoad,
+            DomainAction::Block => MediaDecision::Blocked(format!("{} is blocked", host)),
+        }
+    }
+
+    // The most specific rule wins
+    pub fn domain_action(&self, host: &str) -> DomainAction {
+        self.domains.iter()
+            .filter(|rule| host_matches(host, &rule.domain))
+            .max_by_key(|rule| rule.domain.len())
+            .map_or(self.unlisted, |rule| rule.action)
+    }
+
+    pub fn set_domain_action(&mut self, domain: &str, action: DomainAction) {
+        let domain = domain.trim().trim_start_matches('.').to_lowercase();
+        match self.domains.iter_mut().find(|rule| rule.domain == domain) {
+            Some(rule) => rule.action = action,
+            None => self.domains.push(DomainRule { domain, action }),
+        }
+    }
+
+    fn type_rejection(&self, url: &Url, mime: Option<&str>) -> Option<String> {
+        if let Some(mime) = mime {
+            let mime = mime.split(';').next().unwrap_or(mime).trim().to_lowercase();
+            let allowed = self.mime_types.iter().any(|allowed| match allowed.strip_suffix("/*") {
+                Some(kind) => mime.split('/').next() == Some(kind),
+                None => *allowed == mime,
+            });
+            return (!allowed).then(|| format!("{} isn't allowed", mime));
+        }
+        let path = url.path().to_lowercase();
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension).filter(|ext| !ext.contains('/'));
+        match extension {
+            Some(extension) if self.extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(extension)) => None,
+            Some(extension) => Some(format!(".{} isn't allowed", extension)),
+            None => Some("unknown file type".into()),
+        }
+    }
+}
+
+pub fn host_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches('.');
+    host == domain || host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discord_cdn_autoloads() {
+        let policy = MediaPolicy::default();
+        assert_eq!(policy.decide("https://cdn.discordapp.com/attachments/1/2/cat.png", None), MediaDecision::Load);
+        assert_eq!(policy.decide("https://cdn.discordapp.com/attachments/1/2/cat.PNG?ex=1", None), MediaDecision::Load);
+    }
+
+    #[test]
+    fn lookalike_hosts_are_not_trusted() {
+        let policy = MediaPolicy::default();
+        assert_eq!(policy.decide("https://cdn.discordapp.com.evil.net/cat.png", None), MediaDecision::ClickToLoad);
+        assert_eq!(policy.decide("https://cdn.discordapp.com@evil.net/cat.png", None), MediaDecision::ClickToLoad);
+        assert_eq!(policy.decide("https://evilcdn.discordapp.com.net/cat.png", None), MediaDecision::ClickToLoad);
+    }
+
+    #[test]
+    fn subdomains_follow_their_domain() {
+        let mut policy = MediaPolicy::default();
+        policy.set_domain_action("imgur.com", DomainAction::Autoload);
+        policy.set_domain_action("ads.imgur.com", DomainAction::Block);
+        assert_eq!(policy.decide("https://i.imgur.com/a.jpg", None), MediaDecision::Load);
+        assert!(matches!(policy.decide("https://ads.imgur.com/a.jpg", None), MediaDecision::Blocked(_)));
+        assert_eq!(policy.decide("https://notimgur.com/a.jpg", None), MediaDecision::ClickToLoad);
+    }
+
+    #[test]
+    fn type_rules() {
+        let mut policy = MediaPolicy::default();
+        assert!(matches!(policy.decide("https://cdn.discordapp.com/a.svg", None), MediaDecision::Blocked(_)));
+        assert!(matches!(policy.decide("https://cdn.discordapp.com/a", None), MediaDecision::Blocked(_)));
+        assert_eq!(policy.decide("https://cdn.discordapp.com/a", Some("image/png")), MediaDecision::Load);
+        assert!(matches!(policy.decide("https://cdn.discordapp.com/a.png", Some("text/html")), MediaDecision::Blocked(_)));
+        policy.mime_types = vec!["image/*".into()];
+        assert_eq!(policy.decide("https://cdn.discordapp.com/a", Some("image/avif")), MediaDecision::Load);
+    }
+
+    #[test]
+    fn only_https() {
+        let policy = MediaPolicy::default();
+        assert!(matches!(policy.decide("http://cdn.discordapp.com/a.png", None), MediaDecision::Blocked(_)));
+        assert!(matches!(policy.decide("not a url", None), MediaDecision::Blocked(_)));
+    }
+}
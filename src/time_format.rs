@@ -0,0 +1,500 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, OffsetDateTime, UtcOffset};
+
+// How times are shown, configured under "time" in the config file.
+// Named zones are read from the system's tz database (TZif files), so no zone data is bundled.
+// Systems without one (Windows) only get offsets unless TZDIR points at a copy.
+// Past the last transition of a file its POSIX TZ footer takes over.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeSettings {
+    pub zone: String, // an IANA name like "Europe/Warsaw", "Local", or an offset like "UTC+2" or "-03:30"
+    pub clock_24h: bool,
+    pub relative: bool, // "5 minutes ago" rather than the date and time
+}
+
+impl Default for TimeSettings {
+    fn default() -> Self {
+        Self{
+            zone: "UTC".into(),
+            clock_24h: true,
+            relative: false,
+        }
+    }
+}
+
+const DAY_SECS: i64 = 24 * 3600;
+const UNIX_EPOCH_JULIAN_DAY: i64 = 2_440_588;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Zone {
+    Fixed(i32), // seconds east of UTC
+    Rules(TzData),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TzData {
+    transitions: Vec<(i64, i32)>, // from this unix time on, this offset
+    initial: i32,
+    footer: Option<PosixTz>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PosixTz {
+    std: i32,
+    dst: Option<Dst>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Dst {
+    offset: i32,
+    start: (DayRule, i32), // the day and the local time in seconds
+    end: (DayRule, i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DayRule {
+    NoLeap(u16), // Jn, 1 to 365, February 29th is never counted
+    Ordinal(u16), // n, 0 to 365
+    Weekday(u8, u8, u8), // Mm.w.d, the w-th d (0 is Sunday) of month m, 5 is the last
+}
+
+pub struct TimeFormat {
+    settings: TimeSettings,
+    zone: Zone,
+    error: Option<String>, // why the zone couldn't be used, UTC is shown instead
+}
+
+impl TimeFormat {
+    pub fn new(settings: &TimeSettings) -> Self {
+        let (zone, error) = match parse_zone(&settings.zone) {
+            Ok(zone) => (zone, None),
+            Err(err) => (Zone::Fixed(0), Some(err)),
+        };
+        Self{ settings: settings.clone(), zone, error }
+    }
+
+    pub fn settings(&self) -> &TimeSettings {
+        &self.settings
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn offset_at(&self, secs: i64) -> i32 {
+        match &self.zone {
+            Zone::Fixed(offset) => *offset,
+            Zone::Rules(data) => data.offset_at(secs),
+        }
+    }
+
+    fn local(&self, secs: i64) -> Option<OffsetDateTime> {
+        let offset = UtcOffset::from_whole_seconds(self.offset_at(secs)).ok()?;
+        Some(OffsetDateTime::from_unix_timestamp(secs).ok()?.to_offset(offset))
+    }
+
+    pub fn same_day(&self, a: i64, b: i64) -> bool {
+        let day = |secs: i64| (secs + self.offset_at(secs) as i64).div_euclid(DAY_SECS);
+        day(a) == day(b)
+    }
+
+    fn clock(&self, date: &OffsetDateTime, seconds: bool) -> String {
+        let seconds = if seconds { format!(":{:02}", date.second()) } else { "".into() };
+        if self.settings.clock_24h {
+            return format!("{:02}:{:02}{}", date.hour(), date.minute(), seconds);
+        }
+        let hour = match date.hour() % 12 {
+            0 => 12,
+            hour => hour,
+        };
+        let period = if date.hour() < 12 { "AM" } else { "PM" };
+        format!("{}:{:02}{} {}", hour, date.minute(), seconds, period)
+    }
+
+    // Discord timestamp styles: t, T, d, D, f (default), F and R
+    pub fn format(&self, secs: i64, style: char) -> String {
+        if style == 'R' {
+            return format_relative(secs);
+        }
+        let Some(date) = self.local(secs) else {
+            return secs.to_string();
+        };
+        let long_date = format!("{} {} {}", date.day(), date.month(), date.year());
+        match style {
+            't' => self.clock(&date, false),
+            'T' => self.clock(&date, true),
+            'd' => format!("{}-{:02}-{:02}", date.year(), date.month() as u8, date.day()),
+            'D' => long_date,
+            'F' => format!("{}, {} {}", date.weekday(), long_date, self.clock(&date, false)),
+            _ => format!("{} {}", long_date, self.clock(&date, false)),
+        }
+    }
+
+    // Message times, audit log entries and profile dates follow the relative setting
+    pub fn datetime(&self, secs: i64) -> String {
+        if self.settings.relative {
+            format_relative(secs)
+        } else {
+            self.format(secs, 'f')
+        }
+    }
+
+    pub fn datetime_millis(&self, millis: i64) -> String {
+        self.datetime(millis.div_euclid(1000))
+    }
+}
+
+pub fn format_relative(secs: i64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as i64);
+    let delta = secs - now;
+    let units = [("year", 365 * 24 * 3600), ("month", 30 * 24 * 3600), ("day", 24 * 3600), ("hour", 3600), ("minute", 60), ("second", 1)];
+    let (unit, size) = units.iter()
+        .find(|(_, size)| delta.abs() >= *size)
+        .unwrap_or(&("second", 1));
+    let amount = delta.abs() / size;
+    let plural = if amount == 1 { "" } else { "s" };
+    if delta >= 0 {
+        format!("in {} {}{}", amount, unit, plural)
+    } else {
+        format!("{} {}{} ago", amount, unit, plural)
+    }
+}
+
+#[cfg(unix)]
+const SYSTEM_ZONEINFO: Option<&str> = Some("/usr/share/zoneinfo");
+#[cfg(not(unix))]
+const SYSTEM_ZONEINFO: Option<&str> = None;
+
+fn parse_zone(zone: &str) -> Result<Zone, String> {
+    let zone = zone.trim();
+    if let Some(offset) = parse_fixed_offset(zone) {
+        return Ok(Zone::Fixed(offset));
+    }
+    let Some(directory) = std::env::var("TZDIR").ok().or(SYSTEM_ZONEINFO.map(String::from)) else {
+        return Err("Named time zones need a tz database, use an offset like UTC+2 or set TZDIR".into());
+    };
+    let path = if zone.eq_ignore_ascii_case("local") {
+        PathBuf::from("/etc/localtime")
+    } else {
+        let valid = !zone.is_empty() && !zone.contains("..")
+            && zone.chars().all(|c| c.is_ascii_alphanumeric() || "/_+-".contains(c));
+        if !valid {
+            return Err(format!("\"{}\" isn't a time zone", zone));
+        }
+        if !PathBuf::from(&directory).is_dir() {
+            return Err(format!("No tz database at {}, use an offset like UTC+2", directory));
+        }
+        PathBuf::from(directory).join(zone)
+    };
+    let bytes = fs::read(&path).map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+    parse_tzif(&bytes).map(Zone::Rules).ok_or(format!("{} isn't a valid TZif file", path.display()))
+}
+
+// "UTC", "Z", "+2", "-03:30", "UTC+05:30", "GMT-4" or "+0530", east of UTC is positive
+fn parse_fixed_offset(zone: &str) -> Option<i32> {
+    let upper = zone.to_ascii_uppercase();
+    let rest = upper.strip_prefix("UTC").or(upper.strip_prefix("GMT")).unwrap_or(&upper);
+    if rest.is_empty() || rest == "Z" {
+        return (!upper.is_empty()).then_some(0);
+    }
+    let (sign, digits) = match rest.as_bytes()[0] {
+        b'+' => (1, &rest[1..]),
+        b'-' => (-1, &rest[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match digits.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if digits.len() == 4 => digits.split_at(2),
+        None => (digits, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+impl TzData {
+    fn offset_at(&self, secs: i64) -> i32 {
+        let index = self.transitions.partition_point(|(time, _)| *time <= secs);
+        match &self.footer {
+            Some(footer) if index == self.transitions.len() => footer.offset_at(secs),
+            _ if index == 0 => self.initial,
+            _ => self.transitions[index - 1].1,
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + len)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+struct Counts {
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+fn read_header(reader: &mut Reader) -> Option<(u8, Counts)> {
+    if reader.take(4)? != b"TZif" {
+        return None;
+    }
+    let version = reader.take(1)?[0];
+    reader.take(15)?;
+    let mut count = || reader.u32().map(|count| count as usize);
+    let counts = Counts {
+        isutcnt: count()?,
+        isstdcnt: count()?,
+        leapcnt: count()?,
+        timecnt: count()?,
+        typecnt: count()?,
+        charcnt: count()?,
+    };
+    Some((version, counts))
+}
+
+// RFC 8536, version 2 and later files repeat the data with 64 bit times and end with a POSIX TZ string
+fn parse_tzif(bytes: &[u8]) -> Option<TzData> {
+    let mut reader = Reader { bytes, position: 0 };
+    let (version, mut counts) = read_header(&mut reader)?;
+    let mut time_size = 4;
+    if version >= b'2' {
+        let v1_len = counts.timecnt * 5 + counts.typecnt * 6 + counts.charcnt
+            + counts.leapcnt * 8 + counts.isstdcnt + counts.isutcnt;
+        reader.take(v1_len)?;
+        counts = read_header(&mut reader)?.1;
+        time_size = 8;
+    }
+    let times = reader.take(counts.timecnt * time_size)?;
+    let indices = reader.take(counts.timecnt)?;
+    let types: Vec<i32> = reader.take(counts.typecnt * 6)?
+        .chunks(6)
+        .map(|info| i32::from_be_bytes([info[0], info[1], info[2], info[3]]))
+        .collect();
+    reader.take(counts.charcnt + counts.leapcnt * (time_size + 4) + counts.isstdcnt + counts.isutcnt)?;
+    let mut transitions = Vec::with_capacity(counts.timecnt);
+    for (time, index) in times.chunks(time_size).zip(indices) {
+        let time = match time_size {
+            8 => i64::from_be_bytes(time.try_into().ok()?),
+            _ => i32::from_be_bytes(time.try_into().ok()?) as i64,
+        };
+        transitions.push((time, *types.get(*index as usize)?));
+    }
+    let footer = if version >= b'2' {
+        let rest = std::str::from_utf8(&bytes[reader.position..]).ok()?;
+        rest.trim_matches('\n').lines().next().and_then(parse_posix_tz)
+    } else {
+        None
+    };
+    Some(TzData { transitions, initial: *types.first()?, footer })
+}
+
+impl PosixTz {
+    fn offset_at(&self, secs: i64) -> i32 {
+        let Some(dst) = &self.dst else {
+            return self.std;
+        };
+        let Ok(date) = OffsetDateTime::from_unix_timestamp(secs + self.std as i64) else {
+            return self.std;
+        };
+        let year = date.year();
+        // the start is given in standard time, the end in daylight time
+        let start = rule_day(year, dst.start.0) + dst.start.1 as i64 - self.std as i64;
+        let end = rule_day(year, dst.end.0) + dst.end.1 as i64 - dst.offset as i64;
+        let in_dst = if start < end {
+            start <= secs && secs < end
+        } else {
+            // southern hemisphere, daylight time spans the new year
+            !(end <= secs && secs < start)
+        };
+        if in_dst { dst.offset } else { self.std }
+    }
+}
+
+// Midnight of the rule's day in unix seconds, as if it were UTC
+fn rule_day(year: i32, rule: DayRule) -> i64 {
+    let first_of_year = Date::from_ordinal_date(year, 1).map_or(0, |date| date.to_julian_day() as i64);
+    let julian_day = match rule {
+        DayRule::Ordinal(day) => first_of_year + day as i64,
+        DayRule::NoLeap(day) => {
+            let leap = time::util::is_leap_year(year) && day >= 60;
+            first_of_year + day as i64 - 1 + leap as i64
+        }
+        DayRule::Weekday(month, week, weekday) => {
+            let Ok(month) = Month::try_from(month) else {
+                return 0;
+            };
+            let Ok(first) = Date::from_calendar_date(year, month, 1) else {
+                return 0;
+            };
+            let first_weekday = first.weekday().number_days_from_sunday();
+            let mut day = 1 + (weekday + 7 - first_weekday) % 7 + (week - 1) * 7;
+            while day > month.length(year) {
+                day -= 7;
+            }
+            first.to_julian_day() as i64 + day as i64 - 1
+        }
+    };
+    (julian_day - UNIX_EPOCH_JULIAN_DAY) * DAY_SECS
+}
+
+// "CET-1CEST,M3.5.0,M10.5.0/3", POSIX offsets are west of UTC so the sign is flipped
+fn parse_posix_tz(text: &str) -> Option<PosixTz> {
+    let mut rest = skip_name(text)?;
+    let (std, after) = parse_hms(rest)?;
+    rest = after;
+    if rest.is_empty() {
+        return Some(PosixTz { std: -std, dst: None });
+    }
+    rest = skip_name(rest)?;
+    let mut dst_offset = -std + 3600;
+    if !rest.is_empty() && !rest.starts_with(',') {
+        let (offset, after) = parse_hms(rest)?;
+        dst_offset = -offset;
+        rest = after;
+    }
+    // US rules when none are given
+    let rules = rest.strip_prefix(',').unwrap_or("M3.2.0,M11.1.0");
+    let (start, end) = rules.split_once(',')?;
+    let dst = Dst { offset: dst_offset, start: parse_rule(start)?, end: parse_rule(end)? };
+    Some(PosixTz { std: -std, dst: Some(dst) })
+}
+
+fn skip_name(text: &str) -> Option<&str> {
+    if let Some(quoted) = text.strip_prefix('<') {
+        return quoted.split_once('>').map(|(_, rest)| rest);
+    }
+    let len = text.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(text.len());
+    (len >= 3).then(|| &text[len..])
+}
+
+// [+-]hh[:mm[:ss]], returns the seconds and what follows
+fn parse_hms(text: &str) -> Option<(i32, &str)> {
+    let (sign, text) = match text.as_bytes().first()? {
+        b'-' => (-1, &text[1..]),
+        b'+' => (1, &text[1..]),
+        _ => (1, text),
+    };
+    let len = text.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(text.len());
+    let mut seconds = 0;
+    for (index, part) in text[..len].split(':').enumerate() {
+        let value: i32 = part.parse().ok()?;
+        seconds += value * [3600, 60, 1].get(index)?;
+    }
+    Some((sign * seconds, &text[len..]))
+}
+
+fn parse_rule(text: &str) -> Option<(DayRule, i32)> {
+    let (day, time) = match text.split_once('/') {
+        Some((day, time)) => (day, parse_hms(time)?.0),
+        None => (text, 2 * 3600),
+    };
+    let rule = if let Some(day) = day.strip_prefix('J') {
+        DayRule::NoLeap(day.parse().ok()?)
+    } else if let Some(rule) = day.strip_prefix('M') {
+        let mut parts = rule.split('.').map(|part| part.parse::<u8>().ok());
+        let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+            return None;
+        }
+        DayRule::Weekday(month, week, weekday)
+    } else {
+        DayRule::Ordinal(day.parse().ok()?)
+    };
+    Some((rule, time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(zone: Zone, clock_24h: bool) -> TimeFormat {
+        let settings = TimeSettings { zone: "".into(), clock_24h, relative: false };
+        TimeFormat { settings, zone, error: None }
+    }
+
+    const JULY_2024: i64 = 1_720_000_000; // Wed 3 July 2024 09:46:40 UTC
+    const JANUARY_2024: i64 = 1_705_000_000; // Thu 11 January 2024 19:06:40 UTC
+
+    #[test]
+    fn fixed_offsets() {
+        assert_eq!(parse_fixed_offset("UTC"), Some(0));
+        assert_eq!(parse_fixed_offset("+2"), Some(7200));
+        assert_eq!(parse_fixed_offset("UTC-03:30"), Some(-12600));
+        assert_eq!(parse_fixed_offset("gmt+0530"), Some(19800));
+        assert_eq!(parse_fixed_offset("Europe/Warsaw"), None);
+        assert_eq!(parse_fixed_offset("+15"), None);
+    }
+
+    #[test]
+    fn clock_styles() {
+        let utc = format(Zone::Fixed(0), true);
+        assert_eq!(utc.format(JULY_2024, 't'), "09:46");
+        assert_eq!(utc.format(JULY_2024, 'F'), "Wednesday, 3 July 2024 09:46");
+        let twelve = format(Zone::Fixed(5 * 3600), false);
+        assert_eq!(twelve.format(JULY_2024, 'T'), "2:46:40 PM");
+        assert_eq!(twelve.format(JANUARY_2024, 't'), "12:06 AM");
+        assert_eq!(twelve.format(JANUARY_2024, 'd'), "2024-01-12");
+    }
+
+    #[test]
+    fn posix_rules() {
+        let warsaw = parse_posix_tz("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(warsaw.offset_at(JULY_2024), 7200);
+        assert_eq!(warsaw.offset_at(JANUARY_2024), 3600);
+        // 31 March 2024, 01:00 UTC
+        assert_eq!(warsaw.offset_at(1_711_846_800 - 1), 3600);
+        assert_eq!(warsaw.offset_at(1_711_846_800), 7200);
+        let sydney = parse_posix_tz("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.offset_at(JULY_2024), 10 * 3600);
+        assert_eq!(sydney.offset_at(JANUARY_2024), 11 * 3600);
+        let kolkata = parse_posix_tz("IST-5:30").unwrap();
+        assert_eq!(kolkata.offset_at(JULY_2024), 19800);
+        assert_eq!(parse_posix_tz("<+0330>-3:30").unwrap().offset_at(JULY_2024), 12600);
+    }
+
+    #[test]
+    fn local_days() {
+        let utc = format(Zone::Fixed(0), true);
+        let tokyo = format(Zone::Fixed(9 * 3600), true);
+        // 14:00 and 16:00 UTC are the same day in UTC but not in Tokyo
+        let afternoon = 1_720_015_200;
+        assert!(utc.same_day(afternoon, afternoon + 7200));
+        assert!(!tokyo.same_day(afternoon, afternoon + 7200));
+    }
+
+    #[test]
+    fn system_zone_files() {
+        // only where the tz database is installed
+        let Ok(zone) = parse_zone("America/New_York") else {
+            return;
+        };
+        let new_york = format(zone, true);
+        assert_eq!(new_york.offset_at(JULY_2024), -4 * 3600);
+        assert_eq!(new_york.offset_at(JANUARY_2024), -5 * 3600);
+        // far past the transitions written in the file
+        assert_eq!(new_york.offset_at(4_102_444_800 + 180 * 24 * 3600), -4 * 3600);
+    }
+}